@@ -1,15 +1,26 @@
-use crate::providers::{NuObjectStore, parse_url};
+use crate::{
+    config::PluginConfig,
+    error::CloudError,
+    progress::Progress,
+    providers::{ClientSettings, MemoryStores, NuObjectStore, parse_url},
+};
 use async_lock::{Mutex, MutexGuard};
-use bytes::Bytes;
+use bytes::{Bytes, BytesMut};
+use futures::StreamExt;
 use nu_plugin::EngineInterface;
-use nu_protocol::{ShellError, Span, Spanned};
-use object_store::{GetOptions, ObjectStoreExt, path::Path};
+use nu_protocol::{DynamicSuggestion, ShellError, Span, Spanned};
+use object_store::{GetOptions, GetResult, ObjectStoreExt, path::Path};
 use std::{
     collections::HashMap,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
     time::{Duration, Instant},
 };
-use url::Url;
+use url::{Position, Url};
 
+#[derive(Clone)]
 pub struct CacheEntry {
     path: Path,
     /// Data returned by last request
@@ -18,6 +29,8 @@ pub struct CacheEntry {
     e_tag: String,
     /// Instant of last refresh
     refreshed_at: Instant,
+    /// Number of times the entry was returned without fetching its data again
+    hits: u64,
     /// Object store used for this file.
     /// todo: ideally there would be a way to reuse this for multiple paths in a generic way
     store: NuObjectStore,
@@ -25,83 +38,276 @@ pub struct CacheEntry {
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum ObjectStoreCacheKey {
-    Memory,
     Local,
-    AmazonS3 { bucket: String, region: String },
+    AmazonS3 {
+        bucket: String,
+        region: String,
+        endpoint: Option<String>,
+        settings: Arc<ClientSettings>,
+    },
 }
 
-impl From<&NuObjectStore> for ObjectStoreCacheKey {
-    fn from(value: &NuObjectStore) -> Self {
-        match value {
-            NuObjectStore::Memory(_) => ObjectStoreCacheKey::Memory,
-            NuObjectStore::Local(_) => ObjectStoreCacheKey::Local,
-            NuObjectStore::AmazonS3 { bucket, region, .. } => ObjectStoreCacheKey::AmazonS3 {
-                bucket: bucket.to_owned(),
-                region: region.to_owned(),
-            },
-            NuObjectStore::GoogleCloudStorage(_) => unimplemented!(),
-            NuObjectStore::MicrosoftAzure(_) => unimplemented!(),
-            NuObjectStore::Http(_) => unimplemented!(),
+impl std::fmt::Display for ObjectStoreCacheKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ObjectStoreCacheKey::Local => write!(f, "local"),
+            ObjectStoreCacheKey::AmazonS3 { bucket, region, .. } => {
+                write!(f, "s3://{bucket} ({region})")
+            }
         }
     }
 }
 
-/// Example cache that checks entries after 10 seconds for a new version
+/// What failed when a file can't be fetched
+pub const READ_ERROR: &str = "Could not read file";
+
+/// How long listings are reused to complete url arguments
+const COMPLETION_TTL: Duration = Duration::from_secs(30);
+
+type Entries = Arc<Mutex<HashMap<Url, CacheEntry>>>;
+type Stores = Arc<Mutex<HashMap<ObjectStoreCacheKey, NuObjectStore>>>;
+
+/// Example cache that checks entries after 10 seconds for a new version.
+///
+/// Plugin GC is disabled while stores are cached. Once the cache has not been used for the
+/// configured idle timeout, it is cleared and GC is enabled again.
 #[derive(Default)]
 pub struct Cache {
-    entries: Mutex<HashMap<Url, CacheEntry>>,
-    stores: Mutex<HashMap<ObjectStoreCacheKey, NuObjectStore>>,
+    entries: Entries,
+    stores: Stores,
+    pub memory: Arc<MemoryStores>,
+    activity: Arc<Activity>,
+    /// Number of gets answered from the cache since the plugin started
+    hits: AtomicU64,
+    /// Number of gets that fetched data since the plugin started
+    misses: AtomicU64,
+    /// Completions of url arguments, by the listed url
+    completions: Mutex<HashMap<String, (Instant, Vec<DynamicSuggestion>)>>,
+}
+
+/// A cached file, as listed by `cloud cache list`
+pub struct CachedFile {
+    pub url: Url,
+    pub size: u64,
+    pub e_tag: String,
+    /// Time since the data was fetched or last confirmed to be current
+    pub age: Duration,
+    pub hits: u64,
+}
+
+/// Totals of the cache, as shown by `cloud cache stats`
+pub struct CacheStats {
+    pub files: usize,
+    pub size: u64,
+    pub stores: usize,
+    pub hits: u64,
+    pub misses: u64,
+}
+
+#[derive(Default)]
+struct Activity {
+    last_used: std::sync::Mutex<Option<Instant>>,
+    /// Whether a task is waiting to expire the cache
+    expiry_scheduled: AtomicBool,
+}
+
+impl Activity {
+    fn touch(&self) {
+        if let Ok(mut last_used) = self.last_used.lock() {
+            *last_used = Some(Instant::now());
+        }
+    }
+
+    /// Returns how long the cache has not been used at now
+    fn idle_for(&self, now: Instant) -> Duration {
+        self.last_used
+            .lock()
+            .ok()
+            .and_then(|last_used| last_used.map(|i| now.saturating_duration_since(i)))
+            .unwrap_or_default()
+    }
 }
 
 impl Cache {
+    /// Returns the data for url, fetching it if it is not cached.
+    ///
+    /// The cache lock is not held while data is fetched, so that multiple urls can be
+    /// fetched concurrently.
     pub async fn get(
         &self,
         engine: &EngineInterface,
         url: &Spanned<Url>,
+        settings: &ClientSettings,
+        progress: &Progress,
         span: Span,
     ) -> Result<Bytes, ShellError> {
-        let mut lock = self.entries_cache_lock().await;
-        Ok(match lock.get_mut(&url.item) {
-            Some(e) => match e.refreshed_at.elapsed() < Duration::from_secs(10) {
-                true => e.data.clone(), // Return cached data
-                false => {
-                    // Check if remote version has changed
-                    let opts = GetOptions {
-                        if_none_match: Some(e.e_tag.clone()),
-                        ..GetOptions::default()
-                    };
-                    match e.store.object_store().get_opts(&e.path, opts).await {
-                        Ok(d) => e.data = d.bytes().await.map_err(cache_get_error)?,
-                        Err(object_store::Error::NotModified { .. }) => {} // Data has not changed
-                        Err(e) => return Err(cache_get_error(e)),
+        self.activity.touch();
+        let cached = self.entries_cache_lock().await.get(&url.item).cloned();
+
+        match cached {
+            Some(e) if e.refreshed_at.elapsed() < Duration::from_secs(10) => {
+                self.record_hit(&url.item).await;
+                Ok(e.data) // Return cached data
+            }
+            Some(e) => {
+                // Check if remote version has changed
+                let opts = GetOptions {
+                    if_none_match: Some(e.e_tag.clone()),
+                    ..GetOptions::default()
+                };
+                let (data, e_tag, hit) = match e.store.object_store().get_opts(&e.path, opts).await
+                {
+                    Ok(d) => {
+                        let e_tag = d.meta.e_tag.clone().unwrap_or(e.e_tag);
+                        (
+                            read_body(d, progress)
+                                .await
+                                .map_err(|err| cache_get_error(err, &e.store, url))?,
+                            e_tag,
+                            false,
+                        )
                     }
-                    e.refreshed_at = Instant::now();
-                    e.data.clone()
-                }
-            },
+                    Err(object_store::Error::NotModified { .. }) => (e.data, e.e_tag, true), // Data has not changed
+                    Err(err) => return Err(cache_get_error(err, &e.store, url)),
+                };
+                self.insert(url, e.path, e_tag, data.clone(), e.store, hit)
+                    .await;
+                Ok(data)
+            }
             None => {
                 // Not cached, fetch data
-                let (store, path) = parse_url(engine, self, url, span).await?;
+                let (store, path) = parse_url(engine, self, url, settings, span).await?;
                 let get = store
                     .object_store()
                     .get(&path)
                     .await
-                    .map_err(cache_get_error)?;
+                    .map_err(|e| cache_get_error(e, &store, url))?;
                 let e_tag = get.meta.e_tag.clone();
-                let data = get.bytes().await.map_err(cache_get_error)?;
+                let data = read_body(get, progress)
+                    .await
+                    .map_err(|e| cache_get_error(e, &store, url))?;
                 if let Some(e_tag) = e_tag {
-                    let entry = CacheEntry {
-                        path,
-                        e_tag,
-                        data: data.clone(),
-                        refreshed_at: Instant::now(),
-                        store,
-                    };
-                    lock.insert(url.item.clone(), entry);
+                    self.insert(url, path, e_tag, data.clone(), store, false)
+                        .await;
+                    self.schedule_expiry(engine).await?;
+                } else {
+                    self.misses.fetch_add(1, Ordering::Relaxed);
                 }
-                data
+                Ok(data)
             }
-        })
+        }
+    }
+
+    async fn insert(
+        &self,
+        url: &Spanned<Url>,
+        path: Path,
+        e_tag: String,
+        data: Bytes,
+        store: NuObjectStore,
+        hit: bool,
+    ) {
+        let counter = if hit { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+
+        let mut entries = self.entries_cache_lock().await;
+        let hits = entries.get(&url.item).map(|e| e.hits).unwrap_or_default();
+        let entry = CacheEntry {
+            path,
+            e_tag,
+            data,
+            refreshed_at: Instant::now(),
+            hits: hits + u64::from(hit),
+            store,
+        };
+        entries.insert(url.item.clone(), entry);
+    }
+
+    async fn record_hit(&self, url: &Url) {
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        if let Some(entry) = self.entries_cache_lock().await.get_mut(url) {
+            entry.hits += 1;
+        }
+    }
+
+    /// Returns the cached files, sorted by url
+    pub async fn files(&self) -> Vec<CachedFile> {
+        let mut files: Vec<CachedFile> = self
+            .entries_cache_lock()
+            .await
+            .iter()
+            .map(|(url, entry)| CachedFile {
+                url: url.clone(),
+                size: entry.data.len() as u64,
+                e_tag: entry.e_tag.clone(),
+                age: entry.refreshed_at.elapsed(),
+                hits: entry.hits,
+            })
+            .collect();
+        files.sort_by(|a, b| a.url.as_str().cmp(b.url.as_str()));
+        files
+    }
+
+    /// Returns the keys of the cached stores, sorted by name
+    pub async fn store_keys(&self) -> Vec<ObjectStoreCacheKey> {
+        let mut keys: Vec<ObjectStoreCacheKey> =
+            self.stores_cache_lock().await.keys().cloned().collect();
+        keys.sort_by_cached_key(ToString::to_string);
+        keys
+    }
+
+    /// Returns the completions stored for key within the last [`COMPLETION_TTL`]
+    pub async fn completions(&self, key: &str) -> Option<Vec<DynamicSuggestion>> {
+        let completions = self.completions.lock().await;
+        completions
+            .get(key)
+            .filter(|(listed_at, _)| listed_at.elapsed() < COMPLETION_TTL)
+            .map(|(_, suggestions)| suggestions.clone())
+    }
+
+    pub async fn put_completions(&self, key: String, suggestions: Vec<DynamicSuggestion>) {
+        let mut completions = self.completions.lock().await;
+        completions.retain(|_, (listed_at, _)| listed_at.elapsed() < COMPLETION_TTL);
+        completions.insert(key, (Instant::now(), suggestions));
+    }
+
+    pub async fn stats(&self) -> CacheStats {
+        let entries = self.entries_cache_lock().await;
+        CacheStats {
+            files: entries.len(),
+            size: entries.values().map(|e| e.data.len() as u64).sum(),
+            stores: self.stores_cache_lock().await.len(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Drops the cached files below prefix, keeping the stores. Returns the dropped files.
+    ///
+    /// The path of prefix matches whole segments, i.e. `s3://bucket/logs` matches
+    /// `s3://bucket/logs/a.txt` but not `s3://bucket/logs2.txt`, unless it ends with `/`.
+    pub async fn invalidate(&self, prefix: &Url) -> Vec<CachedFile> {
+        let mut entries = self.entries_cache_lock().await;
+        let urls: Vec<Url> = entries
+            .keys()
+            .filter(|url| is_below(url, prefix))
+            .cloned()
+            .collect();
+        let mut removed: Vec<CachedFile> = urls
+            .into_iter()
+            .filter_map(|url| {
+                let entry = entries.remove(&url)?;
+                Some(CachedFile {
+                    url,
+                    size: entry.data.len() as u64,
+                    e_tag: entry.e_tag,
+                    age: entry.refreshed_at.elapsed(),
+                    hits: entry.hits,
+                })
+            })
+            .collect();
+        removed.sort_by(|a, b| a.url.as_str().cmp(b.url.as_str()));
+        removed
     }
 
     pub async fn put_store(
@@ -110,22 +316,73 @@ impl Cache {
         key: ObjectStoreCacheKey,
         store: NuObjectStore,
     ) -> Result<(), ShellError> {
+        self.activity.touch();
         let mut lock = self.stores_cache_lock().await;
         lock.insert(key, store);
-        engine.set_gc_disabled(true)
+        engine.set_gc_disabled(true)?;
+        drop(lock);
+        self.schedule_expiry(engine).await
+    }
+
+    /// Starts a task expiring the cache once it is idle, unless one is running already
+    async fn schedule_expiry(&self, engine: &EngineInterface) -> Result<(), ShellError> {
+        if self.activity.expiry_scheduled.load(Ordering::SeqCst) {
+            return Ok(());
+        }
+        let idle_timeout = PluginConfig::load(engine).map_err(|e| *e)?.idle_timeout;
+        if !idle_timeout.is_zero() && !self.activity.expiry_scheduled.swap(true, Ordering::SeqCst) {
+            tokio::spawn(expire_when_idle(
+                engine.clone(),
+                self.entries.clone(),
+                self.stores.clone(),
+                self.memory.clone(),
+                self.activity.clone(),
+                idle_timeout,
+            ));
+        }
+        Ok(())
+    }
+
+    /// Runs the idle expiry as if it was now, returning whether the cache was cleared
+    #[cfg(test)]
+    pub async fn expire_if_idle(&self, idle_timeout: Duration, now: Instant) -> bool {
+        clear_if_idle(
+            &self.entries,
+            &self.stores,
+            &self.activity,
+            idle_timeout,
+            now,
+        )
+        .await
+        .is_ok()
     }
 
     pub async fn get_store(&self, key: &ObjectStoreCacheKey) -> Option<NuObjectStore> {
+        self.activity.touch();
         let lock = self.stores_cache_lock().await;
         lock.get(key).cloned()
     }
 
+    /// Clears the cached files and stores, but not the memory stores
     pub async fn clear(&self, engine: &EngineInterface) -> Result<(), ShellError> {
         let mut lock = self.entries_cache_lock().await;
         lock.clear();
         let mut lock = self.stores_cache_lock().await;
         lock.clear();
-        engine.set_gc_disabled(false)
+        self.completions.lock().await.clear();
+        if self.memory.lock().await.is_empty() {
+            engine.set_gc_disabled(false)?;
+        }
+        Ok(())
+    }
+
+    /// Enables plugin GC if no stores are cached, i.e. after memory stores were removed
+    pub async fn enable_gc_if_unused(&self, engine: &EngineInterface) -> Result<(), ShellError> {
+        let stores = self.stores_cache_lock().await;
+        if stores.is_empty() && self.memory.lock().await.is_empty() {
+            engine.set_gc_disabled(false)?;
+        }
+        Ok(())
     }
 
     async fn entries_cache_lock(&self) -> MutexGuard<'_, HashMap<Url, CacheEntry>> {
@@ -139,12 +396,87 @@ impl Cache {
     }
 }
 
-fn cache_get_error(e: impl std::error::Error) -> ShellError {
-    ShellError::GenericError {
-        error: format!("Error fetching data from obect store: {e}"),
-        msg: "".into(),
-        span: None,
-        help: None,
-        inner: vec![],
+/// Waits until the cache has been idle for idle_timeout, then clears it and enables GC
+async fn expire_when_idle(
+    engine: EngineInterface,
+    entries: Entries,
+    stores: Stores,
+    memory: Arc<MemoryStores>,
+    activity: Arc<Activity>,
+    idle_timeout: Duration,
+) {
+    loop {
+        // Holding the stores lock keeps put_store from disabling GC again in between
+        let _stores =
+            match clear_if_idle(&entries, &stores, &activity, idle_timeout, Instant::now()).await {
+                Ok(stores) => stores,
+                Err(remaining) => {
+                    tokio::time::sleep(remaining).await;
+                    continue;
+                }
+            };
+        // Memory stores hold data that would be lost if the plugin was stopped
+        if memory.lock().await.is_empty()
+            && let Err(e) = engine.set_gc_disabled(false)
+        {
+            log::warn!("Could not enable plugin GC: {e}");
+        }
+        activity.expiry_scheduled.store(false, Ordering::SeqCst);
+        return;
+    }
+}
+
+/// Clears the cached files and stores if the cache has been idle for idle_timeout at now,
+/// returning the still locked stores. Otherwise returns how much longer it has to be idle.
+async fn clear_if_idle<'a>(
+    entries: &Entries,
+    stores: &'a Stores,
+    activity: &Activity,
+    idle_timeout: Duration,
+    now: Instant,
+) -> Result<MutexGuard<'a, HashMap<ObjectStoreCacheKey, NuObjectStore>>, Duration> {
+    let idle_for = activity.idle_for(now);
+    if idle_for < idle_timeout {
+        return Err(idle_timeout - idle_for);
+    }
+    let mut stores = stores.lock().await;
+    entries.lock().await.clear();
+    stores.clear();
+    Ok(stores)
+}
+
+/// Returns true if url is in the store of prefix, i.e. has the same scheme and host, and its
+/// path starts with the path segments of prefix
+fn is_below(url: &Url, prefix: &Url) -> bool {
+    if url[..Position::BeforePath] != prefix[..Position::BeforePath] {
+        return false;
+    }
+    let path = prefix.path();
+    match url.path().strip_prefix(path) {
+        Some(rest) => path.ends_with('/') || rest.is_empty() || rest.starts_with('/'),
+        None => false,
     }
 }
+
+/// Reads the body of a get request, reporting the download progress
+pub async fn read_body(get: GetResult, progress: &Progress) -> object_store::Result<Bytes> {
+    let size = get.range.end - get.range.start;
+    progress.add_total(size);
+
+    let mut data = BytesMut::with_capacity(size as usize);
+    let mut chunks = get.into_stream();
+    while let Some(chunk) = chunks.next().await {
+        let chunk = chunk?;
+        progress.advance(chunk.len() as u64);
+        data.extend_from_slice(&chunk);
+    }
+    Ok(data.freeze())
+}
+
+fn cache_get_error(
+    e: object_store::Error,
+    store: &NuObjectStore,
+    url: &Spanned<Url>,
+) -> ShellError {
+    CloudError::new(READ_ERROR, e, store.provider(), url.span).into()
+}
@@ -0,0 +1,75 @@
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
+use nu_protocol::{
+    Category, Example, LabeledError, PipelineData, Signature, SyntaxShape, Type, Value,
+};
+
+use crate::{
+    CloudPlugin,
+    command::{cache::list::file_record, parse_url_arg, resolve_alias},
+};
+
+pub struct CacheInvalidate;
+
+impl PluginCommand for CacheInvalidate {
+    type Plugin = CloudPlugin;
+
+    fn name(&self) -> &str {
+        "cloud cache invalidate"
+    }
+
+    fn signature(&self) -> nu_protocol::Signature {
+        Signature::build("cloud cache invalidate")
+            .input_output_types(vec![(Type::Nothing, Type::table())])
+            .required(
+                "url",
+                SyntaxShape::String,
+                "The url of a file, or a prefix of the urls to invalidate.",
+            )
+            .category(Category::FileSystem)
+    }
+
+    fn description(&self) -> &str {
+        "Drop the cached data of files, so that they are fetched again when they are read."
+    }
+
+    fn extra_description(&self) -> &str {
+        "Unlike `cloud cache-clear`, the cached stores and their authenticated clients are kept. \
+        Returns the dropped files. Prefixes match whole path segments, so `s3://mybucket/logs` \
+        matches `s3://mybucket/logs/a.txt` but not `s3://mybucket/logs2.txt`."
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![
+            Example {
+                description: "Invalidate a single file.",
+                example: "cloud cache invalidate s3://mybucket/data.csv",
+                result: None,
+            },
+            Example {
+                description: "Invalidate every file below a prefix.",
+                example: "cloud cache invalidate s3://mybucket/logs/",
+                result: None,
+            },
+        ]
+    }
+
+    fn run(
+        &self,
+        plugin: &Self::Plugin,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        _input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        let call_span = call.head;
+        let url = resolve_alias(engine, call.req(0)?)
+            .and_then(|url| parse_url_arg(engine, &url, call_span))
+            .map_err(|e| LabeledError::from(*e))?;
+
+        let removed = plugin.rt.block_on(plugin.cache.invalidate(&url.item));
+        let rows = removed
+            .into_iter()
+            .map(|file| file_record(file, call_span))
+            .collect();
+        Ok(PipelineData::Value(Value::list(rows, call_span), None))
+    }
+}
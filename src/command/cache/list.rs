@@ -0,0 +1,91 @@
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
+use nu_protocol::{
+    Category, Example, LabeledError, PipelineData, Signature, Span, Type, Value, record,
+};
+
+use crate::{CloudPlugin, cache::CachedFile};
+
+pub struct CacheList;
+
+impl PluginCommand for CacheList {
+    type Plugin = CloudPlugin;
+
+    fn name(&self) -> &str {
+        "cloud cache list"
+    }
+
+    fn signature(&self) -> nu_protocol::Signature {
+        Signature::build("cloud cache list")
+            .input_output_types(vec![(Type::Nothing, Type::record())])
+            .category(Category::FileSystem)
+    }
+
+    fn description(&self) -> &str {
+        "List the files and stores cached by the plugin."
+    }
+
+    fn extra_description(&self) -> &str {
+        "The age of a file is the time since its data was fetched or last confirmed to be current. \
+        Files older than 10 seconds are checked for changes when they are read again."
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![
+            Example {
+                description: "List the cached files.",
+                example: "cloud cache list | get files",
+                result: None,
+            },
+            Example {
+                description: "List the cached stores, which hold the authenticated clients.",
+                example: "cloud cache list | get stores",
+                result: None,
+            },
+        ]
+    }
+
+    fn run(
+        &self,
+        plugin: &Self::Plugin,
+        _engine: &EngineInterface,
+        call: &EvaluatedCall,
+        _input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        let call_span = call.head;
+        let (files, stores) = plugin
+            .rt
+            .block_on(async { (plugin.cache.files().await, plugin.cache.store_keys().await) });
+
+        let files = files
+            .into_iter()
+            .map(|file| file_record(file, call_span))
+            .collect();
+        let stores = stores
+            .into_iter()
+            .map(|key| Value::string(key.to_string(), call_span))
+            .collect();
+        Ok(PipelineData::Value(
+            Value::record(
+                record!(
+                    "files" => Value::list(files, call_span),
+                    "stores" => Value::list(stores, call_span),
+                ),
+                call_span,
+            ),
+            None,
+        ))
+    }
+}
+
+pub(crate) fn file_record(file: CachedFile, span: Span) -> Value {
+    Value::record(
+        record!(
+            "url" => Value::string(file.url.to_string(), span),
+            "size" => Value::filesize(file.size as i64, span),
+            "etag" => Value::string(file.e_tag, span),
+            "age" => Value::duration(file.age.as_nanos() as i64, span),
+            "hits" => Value::int(file.hits as i64, span),
+        ),
+        span,
+    )
+}
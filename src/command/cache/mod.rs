@@ -0,0 +1,41 @@
+use nu_plugin::PluginCommand;
+use nu_protocol::{Category, PipelineData, Signature, Type, Value};
+
+use crate::CloudPlugin;
+
+pub mod invalidate;
+pub mod list;
+pub mod stats;
+
+pub struct CacheStub;
+
+impl PluginCommand for CacheStub {
+    type Plugin = CloudPlugin;
+
+    fn name(&self) -> &str {
+        "cloud cache"
+    }
+
+    fn signature(&self) -> nu_protocol::Signature {
+        Signature::build("cloud cache")
+            .category(Category::FileSystem)
+            .input_output_types(vec![(Type::Nothing, Type::String)])
+    }
+
+    fn description(&self) -> &str {
+        "Inspect and invalidate the files and stores cached by the plugin"
+    }
+
+    fn run(
+        &self,
+        _plugin: &Self::Plugin,
+        engine: &nu_plugin::EngineInterface,
+        call: &nu_plugin::EvaluatedCall,
+        _input: nu_protocol::PipelineData,
+    ) -> Result<nu_protocol::PipelineData, nu_protocol::LabeledError> {
+        Ok(PipelineData::Value(
+            Value::string(engine.get_help()?, call.head),
+            None,
+        ))
+    }
+}
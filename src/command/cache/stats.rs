@@ -0,0 +1,69 @@
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
+use nu_protocol::{Category, Example, LabeledError, PipelineData, Signature, Type, Value, record};
+
+use crate::CloudPlugin;
+
+pub struct CacheStats;
+
+impl PluginCommand for CacheStats {
+    type Plugin = CloudPlugin;
+
+    fn name(&self) -> &str {
+        "cloud cache stats"
+    }
+
+    fn signature(&self) -> nu_protocol::Signature {
+        Signature::build("cloud cache stats")
+            .input_output_types(vec![(Type::Nothing, Type::record())])
+            .category(Category::FileSystem)
+    }
+
+    fn description(&self) -> &str {
+        "Show the number and size of the cached files and the cache hit rate."
+    }
+
+    fn extra_description(&self) -> &str {
+        "Hits and misses are counted since the plugin started. A read of a cached file that was \
+        confirmed to be unchanged counts as a hit."
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![Example {
+            description: "Show the cache hit rate.",
+            example: "cloud cache stats | get hit_rate",
+            result: None,
+        }]
+    }
+
+    fn run(
+        &self,
+        plugin: &Self::Plugin,
+        _engine: &EngineInterface,
+        call: &EvaluatedCall,
+        _input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        let call_span = call.head;
+        let stats = plugin.rt.block_on(plugin.cache.stats());
+
+        let requests = stats.hits + stats.misses;
+        let hit_rate = if requests > 0 {
+            Value::float(stats.hits as f64 / requests as f64, call_span)
+        } else {
+            Value::nothing(call_span)
+        };
+        Ok(PipelineData::Value(
+            Value::record(
+                record!(
+                    "files" => Value::int(stats.files as i64, call_span),
+                    "size" => Value::filesize(stats.size as i64, call_span),
+                    "stores" => Value::int(stats.stores as i64, call_span),
+                    "hits" => Value::int(stats.hits as i64, call_span),
+                    "misses" => Value::int(stats.misses as i64, call_span),
+                    "hit_rate" => hit_rate,
+                ),
+                call_span,
+            ),
+            None,
+        ))
+    }
+}
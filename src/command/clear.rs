@@ -18,7 +18,7 @@ impl PluginCommand for Clear {
     }
 
     fn description(&self) -> &str {
-        "Clears plugin internal caches. This will also re-enable plugin GC."
+        "Clears plugin internal caches. This will also re-enable plugin GC, unless files are kept in memory stores."
     }
 
     fn examples(&self) -> Vec<Example<'_>> {
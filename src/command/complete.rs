@@ -0,0 +1,242 @@
+use std::time::Duration;
+
+use nu_plugin::{DynamicCompletionCall, EngineInterface};
+use nu_protocol::{DynamicSuggestion, Span, Spanned, SuggestionKind, ast::Expr};
+use object_store::{ObjectStore, path::Path};
+use url::Url;
+
+use crate::{
+    CloudPlugin,
+    config::{Aliases, PluginConfig},
+    providers::{self, DEFAULT_MEMORY_STORE, object_url, store_root_url},
+};
+
+use super::has_scheme;
+
+/// Completion gives up on slower listings, so that it doesn't block typing
+const COMPLETION_TIMEOUT: Duration = Duration::from_secs(3);
+
+const SCHEMES: [&str; 3] = ["s3://", "memory:/", "file:///"];
+
+/// Completes the url argument at the cursor with schemes, aliases, memory stores, s3 buckets
+/// and the objects and directories below the typed prefix. Returns [`None`] for local paths,
+/// so that they are completed like other paths.
+pub(crate) fn complete_url(
+    plugin: &CloudPlugin,
+    engine: &EngineInterface,
+    call: &DynamicCompletionCall,
+) -> Option<Vec<DynamicSuggestion>> {
+    let prefix = typed_prefix(call)?;
+    plugin.rt.block_on(async {
+        tokio::time::timeout(COMPLETION_TIMEOUT, suggestions(plugin, engine, &prefix))
+            .await
+            .ok()
+            .flatten()
+    })
+}
+
+/// Returns the text of the argument at the cursor, up to the cursor
+pub(super) fn typed_prefix(call: &DynamicCompletionCall) -> Option<String> {
+    let expr = call
+        .call
+        .positional_iter()
+        .find(|expr| expr.span.start <= call.pos && call.pos <= expr.span.end)?;
+    let text = match &expr.expr {
+        Expr::String(text) | Expr::RawString(text) | Expr::GlobPattern(text, _) => text,
+        _ => return None,
+    };
+    let mut end = (call.pos - expr.span.start).min(text.len());
+    // The completer inserts a placeholder at the cursor
+    if call.strip {
+        end = end.min(text.len().saturating_sub(1));
+    }
+    text.get(..end).map(ToString::to_string)
+}
+
+async fn suggestions(
+    plugin: &CloudPlugin,
+    engine: &EngineInterface,
+    prefix: &str,
+) -> Option<Vec<DynamicSuggestion>> {
+    let aliases = PluginConfig::load(engine)
+        .map(|config| config.aliases)
+        .unwrap_or_default();
+    // The urls below an alias are completed as urls starting with the alias
+    if let Some((name, base, rest)) = aliases.split(prefix) {
+        let suggestions = url_suggestions(plugin, engine, &format!("{base}{rest}")).await?;
+        return Some(from_alias(suggestions, name, &base));
+    }
+
+    let mut suggestions = url_suggestions(plugin, engine, prefix).await;
+    let alias_suggestions = alias_suggestions(prefix, &aliases);
+    if !alias_suggestions.is_empty() {
+        suggestions
+            .get_or_insert_default()
+            .extend(alias_suggestions);
+    }
+    suggestions
+}
+
+async fn url_suggestions(
+    plugin: &CloudPlugin,
+    engine: &EngineInterface,
+    prefix: &str,
+) -> Option<Vec<DynamicSuggestion>> {
+    let stores: Vec<String> = plugin.cache.memory.lock().await.keys().cloned().collect();
+    let mut suggestions = scheme_suggestions(prefix, &stores);
+    let Some((scheme, rest)) = prefix.split_once(':').filter(|_| has_scheme(prefix)) else {
+        return (!suggestions.is_empty()).then_some(suggestions);
+    };
+
+    match (scheme, rest.strip_prefix("//")) {
+        ("s3" | "s3a", Some(bucket)) if !bucket.contains('/') => {
+            suggestions = bucket_suggestions(plugin, engine, scheme).await?;
+        }
+        // Only list memory stores that exist, listing creates them
+        ("memory", Some(name)) if !name.contains('/') => {}
+        ("memory", authority) => {
+            let name = authority
+                .and_then(|rest| rest.split('/').next())
+                .filter(|name| !name.is_empty())
+                .unwrap_or(DEFAULT_MEMORY_STORE);
+            if stores.iter().any(|store| store == name) {
+                suggestions.extend(listing(plugin, engine, prefix).await.unwrap_or_default());
+            }
+        }
+        _ => suggestions.extend(listing(plugin, engine, prefix).await.unwrap_or_default()),
+    }
+    suggestions.retain(|suggestion| suggestion.value.starts_with(prefix));
+    Some(suggestions)
+}
+
+/// Returns the schemes and named memory stores that start with prefix
+pub(super) fn scheme_suggestions(prefix: &str, memory_stores: &[String]) -> Vec<DynamicSuggestion> {
+    let mut roots: Vec<String> = SCHEMES.iter().map(ToString::to_string).collect();
+    let mut stores: Vec<&String> = memory_stores
+        .iter()
+        .filter(|store| *store != DEFAULT_MEMORY_STORE)
+        .collect();
+    stores.sort();
+    roots.extend(stores.into_iter().map(|store| format!("memory://{store}/")));
+
+    roots
+        .into_iter()
+        .filter(|root| root.len() > prefix.len() && root.starts_with(prefix))
+        .map(directory)
+        .collect()
+}
+
+/// Returns the aliases that start with prefix, described by their url
+pub(super) fn alias_suggestions(prefix: &str, aliases: &Aliases) -> Vec<DynamicSuggestion> {
+    aliases
+        .iter()
+        .map(|(name, url)| (format!("{name}:"), url))
+        .filter(|(value, _)| value.len() > prefix.len() && value.starts_with(prefix))
+        .map(|(value, url)| DynamicSuggestion {
+            description: Some(url.to_string()),
+            ..directory(value)
+        })
+        .collect()
+}
+
+/// Replaces base, the url of the alias name, at the start of the suggested urls by the
+/// alias. Suggestions outside of base are dropped.
+pub(super) fn from_alias(
+    suggestions: Vec<DynamicSuggestion>,
+    name: &str,
+    base: &str,
+) -> Vec<DynamicSuggestion> {
+    suggestions
+        .into_iter()
+        .filter_map(|suggestion| {
+            let rest = suggestion.value.strip_prefix(base)?;
+            Some(DynamicSuggestion {
+                value: format!("{name}:{rest}"),
+                ..suggestion
+            })
+        })
+        .collect()
+}
+
+async fn bucket_suggestions(
+    plugin: &CloudPlugin,
+    engine: &EngineInterface,
+    scheme: &str,
+) -> Option<Vec<DynamicSuggestion>> {
+    let key = format!("{scheme}://");
+    if let Some(suggestions) = plugin.cache.completions(&key).await {
+        return Some(suggestions);
+    }
+    let settings = PluginConfig::load(engine).ok()?.client;
+    let buckets = providers::list_buckets(&settings, Span::unknown())
+        .await
+        .ok()?;
+    let suggestions: Vec<DynamicSuggestion> = buckets
+        .iter()
+        .map(|bucket| directory(format!("{key}{bucket}/")))
+        .collect();
+    plugin.cache.put_completions(key, suggestions.clone()).await;
+    Some(suggestions)
+}
+
+/// Lists the directory of the url prefix, i.e. `s3://bucket/logs/` for `s3://bucket/logs/2024`
+async fn listing(
+    plugin: &CloudPlugin,
+    engine: &EngineInterface,
+    prefix: &str,
+) -> Option<Vec<DynamicSuggestion>> {
+    let dir = &prefix[..=prefix.rfind('/')?];
+    if let Some(suggestions) = plugin.cache.completions(dir).await {
+        return Some(suggestions);
+    }
+
+    let url = Spanned {
+        item: Url::parse(dir).ok()?,
+        span: Span::unknown(),
+    };
+    let settings = PluginConfig::load(engine).ok()?.client;
+    let (object_store, path) = plugin
+        .parse_url(engine, &url, &settings, Span::unknown())
+        .await
+        .ok()?;
+    let root = store_root_url(&url.item, &path);
+    let suggestions = list_suggestions(object_store.object_store(), &root, &path)
+        .await
+        .ok()?;
+    plugin
+        .cache
+        .put_completions(dir.to_string(), suggestions.clone())
+        .await;
+    Some(suggestions)
+}
+
+/// Returns the urls of the directories and objects directly below path
+pub(super) async fn list_suggestions(
+    object_store: &dyn ObjectStore,
+    root: &Url,
+    path: &Path,
+) -> object_store::Result<Vec<DynamicSuggestion>> {
+    let listing = object_store
+        .list_with_delimiter(Some(path).filter(|path| !path.is_root()))
+        .await?;
+    let directories = listing
+        .common_prefixes
+        .iter()
+        .map(|prefix| directory(format!("{}/", object_url(root, prefix))));
+    let files = listing.objects.iter().map(|meta| DynamicSuggestion {
+        value: object_url(root, &meta.location).to_string(),
+        kind: Some(SuggestionKind::File),
+        ..DynamicSuggestion::default()
+    });
+    Ok(directories.chain(files).collect())
+}
+
+/// A suggestion that is completed further, so no space is added after it
+fn directory(value: String) -> DynamicSuggestion {
+    DynamicSuggestion {
+        value,
+        append_whitespace: false,
+        kind: Some(SuggestionKind::Directory),
+        ..DynamicSuggestion::default()
+    }
+}
@@ -0,0 +1,102 @@
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
+use nu_protocol::{
+    Category, Example, LabeledError, PipelineData, ShellError, Signature, Spanned, SyntaxShape,
+    Type, Value, record,
+};
+
+use crate::{CloudPlugin, providers};
+
+use super::{client_flags, client_settings, parse_url_arg, resolve_alias};
+
+pub struct Doctor;
+
+impl PluginCommand for Doctor {
+    type Plugin = CloudPlugin;
+
+    fn name(&self) -> &str {
+        "cloud doctor"
+    }
+
+    fn signature(&self) -> nu_protocol::Signature {
+        client_flags(Signature::build("cloud doctor"))
+            .input_output_types(vec![(Type::Nothing, Type::table())])
+            .optional(
+                "url",
+                SyntaxShape::String,
+                "A bucket url to check access to.",
+            )
+            .category(Category::FileSystem)
+    }
+
+    fn description(&self) -> &str {
+        "Check the cloud configuration and suggest fixes for common problems."
+    }
+
+    fn extra_description(&self) -> &str {
+        "For s3 the config file, SSO profile layout, region, credentials and their expiry are checked. \
+        When a bucket url is given, clock skew and whether the bucket can be listed are checked too."
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![
+            Example {
+                description: "Check the AWS configuration.",
+                example: "cloud doctor",
+                result: None,
+            },
+            Example {
+                description: "Check the AWS configuration and access to a bucket.",
+                example: "cloud doctor s3://mybucket/",
+                result: None,
+            },
+            Example {
+                description: "Show only the failed checks.",
+                example: "cloud doctor s3://mybucket/ | where status != ok",
+                result: None,
+            },
+        ]
+    }
+
+    fn run(
+        &self,
+        plugin: &Self::Plugin,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        _input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        plugin
+            .rt
+            .block_on(command(engine, call))
+            .map_err(|e| LabeledError::from(*e))
+    }
+}
+
+async fn command(
+    engine: &EngineInterface,
+    call: &EvaluatedCall,
+) -> Result<PipelineData, Box<ShellError>> {
+    let call_span = call.head;
+    let url = call
+        .opt::<Spanned<String>>(0)?
+        .map(|url| parse_url_arg(engine, &resolve_alias(engine, url)?, call_span))
+        .transpose()?;
+    let settings = client_settings(engine, call)?;
+    let checks = providers::diagnose(url.as_ref(), &settings, call_span).await?;
+
+    let rows = checks
+        .into_iter()
+        .map(|check| {
+            Value::record(
+                record!(
+                    "check" => Value::string(check.name, call_span),
+                    "status" => Value::string(check.status.as_str(), call_span),
+                    "detail" => Value::string(check.detail, call_span),
+                    "fix" => check.fix.map(|f| Value::string(f, call_span)).unwrap_or(Value::nothing(call_span)),
+                ),
+                call_span,
+            )
+        })
+        .collect();
+
+    Ok(PipelineData::Value(Value::list(rows, call_span), None))
+}
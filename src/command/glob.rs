@@ -0,0 +1,155 @@
+use futures::TryStreamExt;
+use nu_glob::{MatchOptions, Pattern};
+use nu_plugin::EngineInterface;
+use nu_protocol::{NuGlob, ShellError, Span, Spanned};
+use object_store::path::Path;
+
+use crate::{
+    CloudPlugin,
+    error::CloudError,
+    providers::{ClientSettings, object_url, store_root_url},
+};
+
+use super::{has_scheme, parse_url_arg};
+
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+    recursive_match_hidden_dir: true,
+};
+
+/// A url containing glob patterns, such as `s3://bucket/logs/2024-*/**/*.json.gz`.
+/// It is split into the longest literal url prefix, which is listed, and the
+/// pattern used to filter the keys below that prefix.
+pub(crate) struct GlobUrl {
+    /// The literal part of the url, i.e. `s3://bucket/logs/`
+    pub url: Spanned<String>,
+    pattern: Pattern,
+}
+
+impl GlobUrl {
+    /// Returns [`None`] if the url contains no glob patterns or was quoted and should
+    /// not be expanded.
+    pub fn parse(url: &Spanned<NuGlob>) -> Result<Option<Self>, Box<ShellError>> {
+        let raw = match &url.item {
+            NuGlob::Expand(raw) if nu_glob::is_glob(raw) => raw,
+            _ => return Ok(None),
+        };
+
+        let glob_start = raw
+            .find(['*', '?', '['])
+            .expect("is_glob found a glob character");
+        let split = raw[..glob_start].rfind('/');
+        // The literal prefix of a local path may be empty, i.e. `*.csv` in the current directory
+        let literal_end = match split {
+            Some(split) if !has_scheme(raw) || raw[..split].contains(':') => split + 1,
+            None if !has_scheme(raw) => 0,
+            _ => {
+                return Err(Box::new(ShellError::IncorrectValue {
+                    msg: "Glob patterns are only supported in the path of a url".into(),
+                    val_span: url.span,
+                    call_span: url.span,
+                }));
+            }
+        };
+
+        let pattern =
+            Pattern::new(&raw[literal_end..]).map_err(|e| ShellError::IncorrectValue {
+                msg: format!("Invalid glob pattern: {e}"),
+                val_span: url.span,
+                call_span: url.span,
+            })?;
+
+        let literal = match &raw[..literal_end] {
+            "" => "./",
+            literal => literal,
+        };
+        Ok(Some(GlobUrl {
+            url: Spanned {
+                item: literal.to_string(),
+                span: url.span,
+            },
+            pattern,
+        }))
+    }
+
+    /// Returns a matcher for keys listed below prefix, the path of the literal url
+    pub fn matcher(&self, prefix: Path) -> GlobMatcher {
+        GlobMatcher {
+            prefix,
+            pattern: self.pattern.clone(),
+        }
+    }
+}
+
+pub(crate) struct GlobMatcher {
+    prefix: Path,
+    pattern: Pattern,
+}
+
+impl GlobMatcher {
+    pub fn matches(&self, location: &Path) -> bool {
+        location.prefix_match(&self.prefix).is_some_and(|parts| {
+            let relative = parts
+                .map(|part| part.as_ref().to_string())
+                .collect::<Vec<_>>()
+                .join("/");
+            self.pattern.matches_with(&relative, MATCH_OPTIONS)
+        })
+    }
+}
+
+/// Expands a url argument into the urls of every object matching it.
+/// Urls without glob patterns are returned as is.
+pub(crate) async fn expand_url(
+    plugin: &CloudPlugin,
+    engine: &EngineInterface,
+    url: Spanned<NuGlob>,
+    settings: &ClientSettings,
+    call_span: Span,
+) -> Result<Vec<Spanned<String>>, Box<ShellError>> {
+    let Some(glob) = GlobUrl::parse(&url)? else {
+        return Ok(vec![Spanned {
+            item: url.item.as_ref().to_string(),
+            span: url.span,
+        }]);
+    };
+
+    let literal_url = parse_url_arg(engine, &glob.url, call_span)?;
+    let (object_store, path) = plugin
+        .parse_url(engine, &literal_url, settings, call_span)
+        .await?;
+    let root = store_root_url(&literal_url.item, &path);
+    let matcher = glob.matcher(path.clone());
+
+    let mut locations: Vec<Path> = object_store
+        .object_store()
+        .list(Some(&path))
+        .map_ok(|meta| meta.location)
+        .try_filter(|location| futures::future::ready(matcher.matches(location)))
+        .try_collect()
+        .await
+        .map_err(|e| {
+            CloudError::new("Could not list files", e, object_store.provider(), url.span)
+        })?;
+
+    if locations.is_empty() {
+        return Err(Box::new(ShellError::GenericError {
+            error: "No matches found".into(),
+            msg: format!("no objects match {}", url.item.as_ref()),
+            span: Some(url.span),
+            help: None,
+            inner: vec![],
+        }));
+    }
+
+    locations.sort();
+    Ok(locations
+        .iter()
+        .map(|location| Spanned {
+            item: object_url(&root, location).to_string(),
+            span: url.span,
+        })
+        .collect())
+}
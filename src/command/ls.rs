@@ -1,14 +1,30 @@
-use std::{path::PathBuf, str::FromStr, vec};
+use std::{collections::VecDeque, vec};
 
-use futures::StreamExt;
-use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
+use futures::{StreamExt, stream::BoxStream};
+use nu_plugin::{DynamicCompletionCall, EngineInterface, EvaluatedCall, PluginCommand};
 use nu_protocol::{
-    Category, Example, LabeledError, PipelineData, ShellError, Signature, Spanned, SyntaxShape,
-    Type, Value, record,
+    Category, DynamicSuggestion, Example, LabeledError, ListStream, PipelineData, ShellError,
+    Signals, Signature, Span, Spanned, SyntaxShape, Type, Value, engine::ArgType, record,
 };
+use object_store::{ObjectMeta, ObjectStoreExt, list::PaginatedListOptions, path::Path};
+use tokio::runtime::Handle;
 use url::Url;
 
-use crate::CloudPlugin;
+use crate::{
+    CloudPlugin,
+    error::CloudError,
+    providers::{
+        NuObjectStore, ObjectVersion, Provider, VersionMarker, object_url, store_root_url,
+        versions_unsupported,
+    },
+};
+
+use super::{
+    client_flags, client_settings,
+    complete::complete_url,
+    glob::{GlobMatcher, GlobUrl},
+    literal_key_flag, literal_url, parse_url_arg, resolve_glob_alias,
+};
 
 pub struct Ls;
 
@@ -20,22 +36,75 @@ impl PluginCommand for Ls {
     }
 
     fn signature(&self) -> nu_protocol::Signature {
-        Signature::build("cloud ls")
-            .required("uri", SyntaxShape::String, "The url to use.")
+        client_flags(literal_key_flag(Signature::build("cloud ls")))
+            .required(
+                "uri",
+                SyntaxShape::GlobPattern,
+                "The url or glob pattern to use.",
+            )
+            .switch(
+                "recursive",
+                "List every object under the url instead of only the first level",
+                Some('r'),
+            )
+            .named(
+                "max-depth",
+                SyntaxShape::Int,
+                "List objects up to the given number of levels below the url",
+                Some('d'),
+            )
+            .named(
+                "start-after",
+                SyntaxShape::String,
+                "Only list objects whose name sorts after the given name",
+                Some('s'),
+            )
+            .switch(
+                "versions",
+                "List every version and delete marker of the objects in a versioned bucket",
+                None,
+            )
             .category(Category::FileSystem)
             .input_output_types(vec![(Type::Nothing, Type::Any)])
     }
 
     fn description(&self) -> &str {
-        "List the filenames, sizes, modificationtime , etags, and versions of a cloud location."
+        "List the filenames, urls, sizes, modificationtime , etags, and versions of a cloud location."
     }
 
     fn examples(&self) -> Vec<Example<'_>> {
-        vec![Example {
-            description: "List the files in a s3 bucket.",
-            example: "cloud ls s3://mybucket",
-            result: None,
-        }]
+        vec![
+            Example {
+                description: "List the files and directories at the top level of a s3 bucket.",
+                example: "cloud ls s3://mybucket",
+                result: None,
+            },
+            Example {
+                description: "List every file in a s3 bucket.",
+                example: "cloud ls --recursive s3://mybucket",
+                result: None,
+            },
+            Example {
+                description: "List the files and directories up to two levels deep.",
+                example: "cloud ls --max-depth 2 s3://mybucket/logs",
+                result: None,
+            },
+            Example {
+                description: "List the json files below a prefix using a glob pattern.",
+                example: "cloud ls s3://mybucket/logs/2024-*/**/*.json.gz",
+                result: None,
+            },
+            Example {
+                description: "Continue a listing after the last object of a previous listing.",
+                example: "cloud ls --recursive --start-after logs/2024-01-31.log s3://mybucket/logs",
+                result: None,
+            },
+            Example {
+                description: "List the versions of a file in a versioned s3 bucket.",
+                example: "cloud ls --versions s3://mybucket/file.txt",
+                result: None,
+            },
+        ]
     }
 
     fn run(
@@ -48,7 +117,22 @@ impl PluginCommand for Ls {
         plugin
             .rt
             .block_on(command(engine, plugin, call))
-            .map_err(LabeledError::from)
+            .map_err(|e| LabeledError::from(*e))
+    }
+
+    #[expect(deprecated, reason = "completion is still experimental in nushell")]
+    fn get_dynamic_completion(
+        &self,
+        plugin: &Self::Plugin,
+        engine: &EngineInterface,
+        call: DynamicCompletionCall,
+        arg_type: ArgType,
+        _experimental: nu_protocol::engine::ExperimentalMarker,
+    ) -> Option<Vec<DynamicSuggestion>> {
+        match arg_type {
+            ArgType::Positional(_) => complete_url(plugin, engine, &call),
+            ArgType::Flag(_) => None,
+        }
     }
 }
 
@@ -56,51 +140,414 @@ async fn command(
     engine: &EngineInterface,
     plugin: &CloudPlugin,
     call: &EvaluatedCall,
-) -> Result<PipelineData, ShellError> {
+) -> Result<PipelineData, Box<ShellError>> {
     let call_span = call.head;
-    let spanned_path: Spanned<PathBuf> = call.req(0)?;
-    let url_path = spanned_path.item;
-    let url = url_path
-        .to_str()
-        .expect("The path should already be unicode")
-        .to_string();
-    let url = Spanned {
-        item: Url::from_str(&url).map_err(|e| ShellError::IncorrectValue {
-            msg: format!("Invalid Url: {e}"),
-            val_span: spanned_path.span,
-            call_span,
-        })?,
-        span: spanned_path.span,
+    let settings = client_settings(engine, call)?;
+    let spanned_url = resolve_glob_alias(engine, call.req(0)?)?;
+    let literal_key = call.has_flag("literal-key")?;
+    let glob = match literal_key {
+        true => None,
+        false => GlobUrl::parse(&spanned_url)?,
     };
+    let url = match &glob {
+        Some(glob) => glob.url.clone(),
+        None => Spanned {
+            item: spanned_url.item.as_ref().to_string(),
+            span: spanned_url.span,
+        },
+    };
+    let url = match literal_key {
+        true => literal_url(&url, call_span)?,
+        false => url,
+    };
+    let url = parse_url_arg(engine, &url, call_span)?;
 
-    let (object_store, path) = plugin.parse_url(engine, &url, call_span).await?;
-    let list_stream = object_store.object_store().list(Some(&path));
-
-    let values: Vec<Value> = list_stream
-        .map(|v| match v {
-            Ok(meta) => Value::record(
-                record!(
-                    "name" => Value::string(meta.location.to_string(), call_span),
-                    "size" => Value::filesize(meta.size as i64, call_span),
-                    "modified" => Value::date(meta.last_modified.fixed_offset(), call_span),
-                    "etag" => meta.e_tag.map(|s| Value::string(s, call_span)).unwrap_or(Value::nothing(call_span)),
-                    "version" => meta.version.map(|s| Value::string(s, call_span)).unwrap_or(Value::nothing(call_span)),
-                ),
+    let recursive = call.has_flag("recursive")?;
+    let max_depth: Option<Spanned<i64>> = call.get_flag("max-depth")?;
+    let max_depth = match max_depth {
+        Some(depth) if depth.item < 1 => {
+            return Err(Box::new(ShellError::IncorrectValue {
+                msg: "max-depth must be at least 1".into(),
+                val_span: depth.span,
                 call_span,
-            ),
-            Err(e) => {
-                let se = ShellError::GenericError {
-                    error: format!("Error fetching data from object store: {e}"),
-                    msg: "".into(),
-                    span: None,
-                    help: None,
-                    inner: vec![],
+            }));
+        }
+        Some(depth) => Some(depth.item as usize),
+        None => None,
+    };
+
+    let start_after: Option<Spanned<String>> = call.get_flag("start-after")?;
+    let start_after = match start_after {
+        Some(offset) => {
+            Some(
+                Path::parse(&offset.item).map_err(|e| ShellError::IncorrectValue {
+                    msg: format!("Invalid start-after key: {e}"),
+                    val_span: offset.span,
+                    call_span,
+                })?,
+            )
+        }
+        None => None,
+    };
+
+    // The levels of a glob are chosen by its pattern, i.e. with `**`
+    if glob.is_some() {
+        for flag in ["recursive", "max-depth"] {
+            if let Some(flag_span) = call.get_flag_span(flag) {
+                return Err(Box::new(ShellError::IncompatibleParameters {
+                    left_message: format!("can't use --{flag}"),
+                    left_span: flag_span,
+                    right_message: "with a glob pattern, use `**` to match every level".into(),
+                    right_span: spanned_url.span,
+                }));
+            }
+        }
+    }
+
+    // Versions are listed for every object below the url, in key order
+    let versions = call.has_flag("versions")?;
+    if let Some(versions_span) = call.get_flag_span("versions") {
+        for flag in ["start-after", "recursive", "max-depth"] {
+            if let Some(flag_span) = call.get_flag_span(flag) {
+                return Err(Box::new(ShellError::IncompatibleParameters {
+                    left_message: format!("can't use --{flag}"),
+                    left_span: flag_span,
+                    right_message: "with --versions, which lists every object below the url".into(),
+                    right_span: versions_span,
+                }));
+            }
+        }
+    }
+    if let (Some(recursive_span), Some(depth_span)) = (
+        call.get_flag_span("recursive"),
+        call.get_flag_span("max-depth"),
+    ) {
+        return Err(Box::new(ShellError::IncompatibleParameters {
+            left_message: "can't use --recursive".into(),
+            left_span: recursive_span,
+            right_message: "with --max-depth, which limits the levels that are listed".into(),
+            right_span: depth_span,
+        }));
+    }
+
+    let (object_store, path) = plugin.parse_url(engine, &url, &settings, call_span).await?;
+    let root = store_root_url(&url.item, &path);
+
+    if versions && !object_store.supports_versions() {
+        return Err(Box::new(versions_unsupported(url.span)));
+    }
+
+    let provider = object_store.provider();
+    let source = match max_depth {
+        _ if versions => ListingSource::Versions {
+            object_store,
+            path: path.clone(),
+            marker: None,
+            matcher: glob.map(|glob| glob.matcher(path)),
+        },
+        // Glob patterns are matched against every key below the literal prefix
+        None if recursive || glob.is_some() => {
+            let stream = match &start_after {
+                Some(offset) => object_store
+                    .object_store()
+                    .list_with_offset(Some(&path), offset),
+                None => object_store.object_store().list(Some(&path)),
+            };
+            ListingSource::Recursive {
+                stream,
+                matcher: glob.map(|glob| glob.matcher(path.clone())),
+            }
+        }
+        max_depth => ListingSource::Delimited {
+            object_store,
+            prefixes: VecDeque::from([(path.clone(), 1)]),
+            current: None,
+            max_depth: max_depth.unwrap_or(1),
+            path,
+            found: false,
+        },
+    };
+
+    let listing = Listing {
+        handle: plugin.rt.handle().clone(),
+        signals: engine.signals().clone(),
+        span: call_span,
+        provider,
+        root,
+        start_after,
+        buffer: VecDeque::new(),
+        source,
+    };
+
+    Ok(PipelineData::ListStream(
+        ListStream::new(listing, call_span, engine.signals().clone()),
+        None,
+    ))
+}
+
+enum ListingSource {
+    /// Every object below the prefix, as returned by [`object_store::ObjectStore::list`]
+    /// optionally filtered to the keys matching a glob pattern
+    Recursive {
+        stream: BoxStream<'static, object_store::Result<ObjectMeta>>,
+        matcher: Option<GlobMatcher>,
+    },
+    /// Files and common prefixes (directories) below path, descending into
+    /// common prefixes until max_depth levels have been listed. Stores that support it
+    /// are listed one page at a time, starting after --start-after.
+    Delimited {
+        object_store: NuObjectStore,
+        prefixes: VecDeque<(Path, usize)>,
+        /// The prefix being paged through, its depth and the token of its next page
+        current: Option<(Path, usize, String)>,
+        max_depth: usize,
+        path: Path,
+        found: bool,
+    },
+    /// Every version and delete marker below path, one page of them at a time
+    Versions {
+        object_store: NuObjectStore,
+        path: Path,
+        /// Where the next page starts, none for the first page
+        marker: Option<VersionMarker>,
+        matcher: Option<GlobMatcher>,
+    },
+    Done,
+}
+
+/// Lazily pages through an object store listing, so that only as many pages as
+/// are consumed downstream are requested.
+struct Listing {
+    handle: Handle,
+    signals: Signals,
+    span: Span,
+    provider: Provider,
+    /// Url of the root of the store, used to build the url of each listed object
+    root: Url,
+    start_after: Option<Path>,
+    buffer: VecDeque<Value>,
+    source: ListingSource,
+}
+
+impl Listing {
+    /// Fetches the next page of results into the buffer
+    fn next_page(&mut self) {
+        let span = self.span;
+        match &mut self.source {
+            ListingSource::Recursive { stream, matcher } => {
+                match self.handle.block_on(stream.next()) {
+                    Some(Ok(meta))
+                        if matcher
+                            .as_ref()
+                            .is_some_and(|matcher| !matcher.matches(&meta.location)) => {}
+                    Some(Ok(meta)) => self.buffer.push_back(file_record(meta, &self.root, span)),
+                    Some(Err(e)) => self
+                        .buffer
+                        .push_back(Value::error(list_error(e, self.provider, span), span)),
+                    None => self.source = ListingSource::Done,
+                }
+            }
+            ListingSource::Delimited {
+                object_store,
+                prefixes,
+                current,
+                max_depth,
+                path,
+                found,
+            } => {
+                let next = match current.take() {
+                    Some((prefix, depth, token)) => Some((prefix, depth, Some(token))),
+                    None => prefixes
+                        .pop_front()
+                        .map(|(prefix, depth)| (prefix, depth, None)),
+                };
+                let Some((prefix, depth, page_token)) = next else {
+                    // Listing a single file returns no children, show the file itself like nushell's ls does
+                    if !*found
+                        && !path.as_ref().is_empty()
+                        && let Ok(meta) =
+                            self.handle.block_on(object_store.object_store().head(path))
+                    {
+                        self.buffer.push_back(file_record(meta, &self.root, span));
+                    }
+                    self.source = ListingSource::Done;
+                    return;
+                };
+
+                let page = match object_store.paginated() {
+                    Some(store) => {
+                        // Unlike list_with_delimiter, the prefix needs a trailing delimiter
+                        let key_prefix = (!prefix.is_root()).then(|| format!("{prefix}/"));
+                        let opts = PaginatedListOptions {
+                            offset: self.start_after.as_ref().map(ToString::to_string),
+                            delimiter: Some("/".into()),
+                            page_token,
+                            ..PaginatedListOptions::default()
+                        };
+                        self.handle
+                            .block_on(store.list_paginated(key_prefix.as_deref(), opts))
+                            .map(|page| (page.result, page.page_token))
+                    }
+                    None => self
+                        .handle
+                        .block_on(
+                            object_store
+                                .object_store()
+                                .list_with_delimiter(Some(&prefix)),
+                        )
+                        .map(|result| (result, None)),
                 };
-                Value::error(se, call_span)
+                let result = match page {
+                    Ok((result, page_token)) => {
+                        if let Some(token) = page_token {
+                            *current = Some((prefix, depth, token));
+                        }
+                        result
+                    }
+                    Err(e) => {
+                        self.buffer
+                            .push_back(Value::error(list_error(e, self.provider, span), span));
+                        self.source = ListingSource::Done;
+                        return;
+                    }
+                };
+
+                for common_prefix in result.common_prefixes {
+                    *found = true;
+                    let after_offset = is_after(&self.start_after, &common_prefix);
+                    let contains_offset = self
+                        .start_after
+                        .as_ref()
+                        .is_some_and(|offset| offset.prefix_matches(&common_prefix));
+                    if after_offset {
+                        self.buffer
+                            .push_back(dir_record(&common_prefix, &self.root, span));
+                    }
+                    if depth < *max_depth && (after_offset || contains_offset) {
+                        prefixes.push_back((common_prefix, depth + 1));
+                    }
+                }
+
+                for meta in result.objects {
+                    *found = true;
+                    if is_after(&self.start_after, &meta.location) {
+                        self.buffer.push_back(file_record(meta, &self.root, span));
+                    }
+                }
+            }
+            ListingSource::Versions {
+                object_store,
+                path,
+                marker,
+                matcher,
+            } => {
+                let page =
+                    self.handle
+                        .block_on(object_store.list_versions(path, marker.take(), span));
+                match page {
+                    Ok((versions, next)) => {
+                        let versions = versions.into_iter().filter(|version| {
+                            matcher.as_ref().is_none_or(|matcher| {
+                                Path::parse(&version.key).is_ok_and(|key| matcher.matches(&key))
+                            })
+                        });
+                        for version in versions {
+                            self.buffer
+                                .push_back(version_record(version, &self.root, span));
+                        }
+                        match next {
+                            Some(next) => *marker = Some(next),
+                            None => self.source = ListingSource::Done,
+                        }
+                    }
+                    Err(e) => {
+                        self.buffer.push_back(Value::error(e, span));
+                        self.source = ListingSource::Done;
+                    }
+                }
+            }
+            ListingSource::Done => {}
+        }
+    }
+}
+
+impl Iterator for Listing {
+    type Item = Value;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(value) = self.buffer.pop_front() {
+                return Some(value);
             }
-        })
-        .collect()
-        .await;
+            if matches!(self.source, ListingSource::Done) || self.signals.interrupted() {
+                return None;
+            }
+            self.next_page();
+        }
+    }
+}
+
+fn is_after(start_after: &Option<Path>, location: &Path) -> bool {
+    start_after
+        .as_ref()
+        .is_none_or(|offset| location.as_ref() > offset.as_ref())
+}
+
+fn file_record(meta: ObjectMeta, root: &Url, span: Span) -> Value {
+    Value::record(
+        record!(
+            "name" => Value::string(meta.location.to_string(), span),
+            "url" => Value::string(object_url(root, &meta.location), span),
+            "type" => Value::string("file", span),
+            "size" => Value::filesize(meta.size as i64, span),
+            "modified" => Value::date(meta.last_modified.fixed_offset(), span),
+            "etag" => meta.e_tag.map(|s| Value::string(s, span)).unwrap_or(Value::nothing(span)),
+            "version" => meta.version.map(|s| Value::string(s, span)).unwrap_or(Value::nothing(span)),
+        ),
+        span,
+    )
+}
+
+fn version_record(version: ObjectVersion, root: &Url, span: Span) -> Value {
+    let url = match Path::parse(&version.key) {
+        Ok(location) => Value::string(object_url(root, &location), span),
+        Err(_) => Value::nothing(span),
+    };
+    let kind = if version.delete_marker {
+        "delete marker"
+    } else {
+        "file"
+    };
+    Value::record(
+        record!(
+            "name" => Value::string(version.key, span),
+            "url" => url,
+            "type" => Value::string(kind, span),
+            "size" => version.size.map(|size| Value::filesize(size as i64, span)).unwrap_or(Value::nothing(span)),
+            "modified" => version.last_modified.map(|date| Value::date(date.fixed_offset(), span)).unwrap_or(Value::nothing(span)),
+            "etag" => version.e_tag.map(|s| Value::string(s, span)).unwrap_or(Value::nothing(span)),
+            "version" => version.version.map(|s| Value::string(s, span)).unwrap_or(Value::nothing(span)),
+            "latest" => Value::bool(version.is_latest, span),
+        ),
+        span,
+    )
+}
+
+fn dir_record(prefix: &Path, root: &Url, span: Span) -> Value {
+    Value::record(
+        record!(
+            "name" => Value::string(prefix.to_string(), span),
+            "url" => Value::string(object_url(root, prefix), span),
+            "type" => Value::string("dir", span),
+            "size" => Value::nothing(span),
+            "modified" => Value::nothing(span),
+            "etag" => Value::nothing(span),
+            "version" => Value::nothing(span),
+        ),
+        span,
+    )
+}
 
-    Ok(PipelineData::Value(Value::list(values, call_span), None))
+fn list_error(e: object_store::Error, provider: Provider, span: Span) -> ShellError {
+    CloudError::new("Could not list files", e, provider, span).into()
 }
@@ -0,0 +1,182 @@
+//! The archive format of `cloud memory export` and `cloud memory import`.
+//!
+//! Archives are plain tar files with an entry per object, named after its key, so that they
+//! can be inspected and extracted with `tar`. Attributes such as the content type and custom
+//! metadata are kept in PAX extended headers with `NU_PLUGIN_CLOUD.` keys.
+
+use std::io::{self, Read, Write};
+
+use futures::TryStreamExt;
+use object_store::{
+    Attribute, Attributes, ObjectMeta, ObjectStore, ObjectStoreExt, PutOptions, PutPayload,
+    path::Path,
+};
+use tar::{Archive, Builder, EntryType, Header};
+
+const PAX_PREFIX: &str = "NU_PLUGIN_CLOUD.";
+const METADATA_PREFIX: &str = "metadata.";
+
+/// Number and total size of the objects written or read
+#[derive(Default)]
+pub struct ArchiveSummary {
+    pub files: usize,
+    pub size: u64,
+}
+
+#[derive(Debug)]
+pub enum ArchiveError {
+    Store(object_store::Error),
+    Io(io::Error),
+    /// An entry that is not a valid object key
+    InvalidKey(String),
+}
+
+impl std::fmt::Display for ArchiveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArchiveError::Store(e) => e.fmt(f),
+            ArchiveError::Io(e) => e.fmt(f),
+            ArchiveError::InvalidKey(key) => write!(f, "{key} is not a valid object key"),
+        }
+    }
+}
+
+impl From<object_store::Error> for ArchiveError {
+    fn from(value: object_store::Error) -> Self {
+        ArchiveError::Store(value)
+    }
+}
+
+impl From<io::Error> for ArchiveError {
+    fn from(value: io::Error) -> Self {
+        ArchiveError::Io(value)
+    }
+}
+
+/// Writes every object of store to writer, sorted by key
+pub async fn export(
+    store: &dyn ObjectStore,
+    writer: impl Write,
+) -> Result<ArchiveSummary, ArchiveError> {
+    let mut objects: Vec<ObjectMeta> = store.list(None).try_collect().await?;
+    objects.sort_by(|a, b| a.location.cmp(&b.location));
+
+    let mut builder = Builder::new(writer);
+    let mut summary = ArchiveSummary::default();
+    for object in objects {
+        let get = store.get(&object.location).await?;
+        let attributes = get.attributes.clone();
+        let data = get.bytes().await?;
+
+        let extensions = pax_extensions(&attributes);
+        if !extensions.is_empty() {
+            let mut header = Header::new_ustar();
+            header.set_entry_type(EntryType::XHeader);
+            header.set_size(extensions.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, "PaxHeaders/object", extensions.as_slice())?;
+        }
+
+        let mut header = Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(object.last_modified.timestamp().max(0) as u64);
+        header.set_cksum();
+        builder.append_data(&mut header, object.location.as_ref(), data.as_ref())?;
+
+        summary.files += 1;
+        summary.size += data.len() as u64;
+    }
+    builder.into_inner()?.flush()?;
+    Ok(summary)
+}
+
+/// Puts every file of the archive read from reader into store, replacing existing objects
+pub async fn import(
+    store: &dyn ObjectStore,
+    reader: impl Read,
+) -> Result<ArchiveSummary, ArchiveError> {
+    let mut archive = Archive::new(reader);
+    let mut summary = ArchiveSummary::default();
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        if entry.header().entry_type() != EntryType::Regular {
+            continue;
+        }
+
+        let key = entry.path()?.to_string_lossy().replace('\\', "/");
+        let path = Path::parse(&key).map_err(|_| ArchiveError::InvalidKey(key.clone()))?;
+        let attributes = match entry.pax_extensions()? {
+            Some(extensions) => extensions
+                .filter_map(|extension| {
+                    let extension = extension.ok()?;
+                    let name = extension.key().ok()?.strip_prefix(PAX_PREFIX)?;
+                    Some((attribute(name)?, extension.value().ok()?.to_string()))
+                })
+                .collect(),
+            None => Attributes::new(),
+        };
+        let mut data = vec![];
+        entry.read_to_end(&mut data)?;
+
+        summary.files += 1;
+        summary.size += data.len() as u64;
+        let opts = PutOptions {
+            attributes,
+            ..PutOptions::default()
+        };
+        store.put_opts(&path, PutPayload::from(data), opts).await?;
+    }
+    Ok(summary)
+}
+
+/// Encodes the attributes as PAX extended header records, sorted so that exports of the
+/// same objects are identical
+fn pax_extensions(attributes: &Attributes) -> Vec<u8> {
+    let mut attributes: Vec<(String, &str)> = attributes
+        .iter()
+        .filter_map(|(attribute, value)| Some((attribute_name(attribute)?, value.as_ref())))
+        .collect();
+    attributes.sort();
+
+    let mut records = vec![];
+    for (name, value) in attributes {
+        let record = format!("{PAX_PREFIX}{name}={value}\n");
+        // Each record starts with its length in bytes, including the length itself
+        let mut len = record.len() + 1;
+        while len != record.len() + 1 + len.to_string().len() {
+            len = record.len() + 1 + len.to_string().len();
+        }
+        records.extend_from_slice(format!("{len} {record}").as_bytes());
+    }
+    records
+}
+
+fn attribute_name(attribute: &Attribute) -> Option<String> {
+    let name = match attribute {
+        Attribute::ContentDisposition => "content_disposition".to_string(),
+        Attribute::ContentEncoding => "content_encoding".to_string(),
+        Attribute::ContentLanguage => "content_language".to_string(),
+        Attribute::ContentType => "content_type".to_string(),
+        Attribute::CacheControl => "cache_control".to_string(),
+        Attribute::StorageClass => "storage_class".to_string(),
+        Attribute::Metadata(key) => format!("{METADATA_PREFIX}{key}"),
+        _ => return None,
+    };
+    Some(name)
+}
+
+fn attribute(name: &str) -> Option<Attribute> {
+    match name {
+        "content_disposition" => Some(Attribute::ContentDisposition),
+        "content_encoding" => Some(Attribute::ContentEncoding),
+        "content_language" => Some(Attribute::ContentLanguage),
+        "content_type" => Some(Attribute::ContentType),
+        "cache_control" => Some(Attribute::CacheControl),
+        "storage_class" => Some(Attribute::StorageClass),
+        name => name
+            .strip_prefix(METADATA_PREFIX)
+            .map(|key| Attribute::Metadata(key.to_string().into())),
+    }
+}
@@ -0,0 +1,108 @@
+use std::{fs::File, io::BufWriter, path::PathBuf};
+
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
+use nu_protocol::{
+    Category, Example, LabeledError, PipelineData, ShellError, Signature, Spanned, SyntaxShape,
+    Type,
+};
+
+use crate::{CloudPlugin, command::local_path, providers::DEFAULT_MEMORY_STORE};
+
+use super::{archive, archive_error, summary_record};
+
+pub struct MemoryExport;
+
+impl PluginCommand for MemoryExport {
+    type Plugin = CloudPlugin;
+
+    fn name(&self) -> &str {
+        "cloud memory export"
+    }
+
+    fn signature(&self) -> nu_protocol::Signature {
+        Signature::build("cloud memory export")
+            .input_output_types(vec![(Type::Nothing, Type::record())])
+            .required(
+                "file",
+                SyntaxShape::Filepath,
+                "The tar archive to write.",
+            )
+            .named(
+                "store",
+                SyntaxShape::String,
+                "the memory store to export, i.e. scratch for memory://scratch/. Defaults to the store of memory:/",
+                Some('s'),
+            )
+            .category(Category::FileSystem)
+    }
+
+    fn description(&self) -> &str {
+        "Write every file of a memory store, with its attributes, to a tar archive."
+    }
+
+    fn extra_description(&self) -> &str {
+        "Attributes such as the content type and custom metadata are kept in PAX headers. \
+        The archive can be loaded back with `cloud memory import`."
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![Example {
+            description: "Save the memory://fixtures/ store.",
+            example: "cloud memory export --store fixtures fixtures.tar",
+            result: None,
+        }]
+    }
+
+    fn run(
+        &self,
+        plugin: &Self::Plugin,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        _input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        plugin
+            .rt
+            .block_on(command(plugin, engine, call))
+            .map_err(|e| LabeledError::from(*e))
+    }
+}
+
+async fn command(
+    plugin: &CloudPlugin,
+    engine: &EngineInterface,
+    call: &EvaluatedCall,
+) -> Result<PipelineData, Box<ShellError>> {
+    let call_span = call.head;
+    let file: Spanned<PathBuf> = call.req(0)?;
+    let name: Spanned<String> = call.get_flag("store")?.unwrap_or(Spanned {
+        item: DEFAULT_MEMORY_STORE.to_string(),
+        span: call_span,
+    });
+
+    let Some(store) = plugin.cache.memory.lock().await.get(&name.item).cloned() else {
+        return Err(Box::new(ShellError::GenericError {
+            error: format!("Memory store {} does not exist", name.item),
+            msg: "no files were saved to this store".into(),
+            span: Some(name.span),
+            help: None,
+            inner: vec![],
+        }));
+    };
+
+    let path = local_path(engine, &file)?;
+    let writer = File::create(&path).map_err(|e| ShellError::GenericError {
+        error: format!("Could not create {}: {e}", path.display()),
+        msg: "".into(),
+        span: Some(file.span),
+        help: None,
+        inner: vec![],
+    })?;
+    let summary = archive::export(store.as_ref(), BufWriter::new(writer))
+        .await
+        .map_err(|e| archive_error("Could not export memory store", e, file.span))?;
+
+    Ok(PipelineData::Value(
+        summary_record(&name.item, &path, summary, call_span),
+        None,
+    ))
+}
@@ -0,0 +1,103 @@
+use std::{fs::File, io::BufReader, path::PathBuf};
+
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
+use nu_protocol::{
+    Category, Example, LabeledError, PipelineData, ShellError, Signature, Spanned, SyntaxShape,
+    Type,
+};
+
+use crate::{CloudPlugin, command::local_path, providers::DEFAULT_MEMORY_STORE};
+
+use super::{archive, archive_error, store_url, summary_record};
+
+pub struct MemoryImport;
+
+impl PluginCommand for MemoryImport {
+    type Plugin = CloudPlugin;
+
+    fn name(&self) -> &str {
+        "cloud memory import"
+    }
+
+    fn signature(&self) -> nu_protocol::Signature {
+        Signature::build("cloud memory import")
+            .input_output_types(vec![(Type::Nothing, Type::record())])
+            .required(
+                "file",
+                SyntaxShape::Filepath,
+                "The tar archive to read.",
+            )
+            .named(
+                "store",
+                SyntaxShape::String,
+                "the memory store to import into, i.e. scratch for memory://scratch/. Defaults to the store of memory:/",
+                Some('s'),
+            )
+            .category(Category::FileSystem)
+    }
+
+    fn description(&self) -> &str {
+        "Load the files of a tar archive, with their attributes, into a memory store."
+    }
+
+    fn extra_description(&self) -> &str {
+        "Files that already exist in the store are replaced, other files are kept. \
+        Run `cloud memory reset` first to load the archive into an empty store."
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![Example {
+            description: "Load test fixtures into the memory://fixtures/ store.",
+            example: "cloud memory reset fixtures; cloud memory import --store fixtures fixtures.tar",
+            result: None,
+        }]
+    }
+
+    fn run(
+        &self,
+        plugin: &Self::Plugin,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        _input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        plugin
+            .rt
+            .block_on(command(plugin, engine, call))
+            .map_err(|e| LabeledError::from(*e))
+    }
+}
+
+async fn command(
+    plugin: &CloudPlugin,
+    engine: &EngineInterface,
+    call: &EvaluatedCall,
+) -> Result<PipelineData, Box<ShellError>> {
+    let call_span = call.head;
+    let file: Spanned<PathBuf> = call.req(0)?;
+    let name: String = call
+        .get_flag("store")?
+        .unwrap_or(DEFAULT_MEMORY_STORE.to_string());
+
+    let path = local_path(engine, &file)?;
+    let reader = File::open(&path).map_err(|e| ShellError::GenericError {
+        error: format!("Could not open {}: {e}", path.display()),
+        msg: "".into(),
+        span: Some(file.span),
+        help: None,
+        inner: vec![],
+    })?;
+    let store = plugin.cache.memory.get_or_create(engine, &name).await?;
+    let summary = archive::import(store.as_ref(), BufReader::new(reader))
+        .await
+        .map_err(|e| archive_error("Could not import memory store", e, file.span))?;
+
+    // Drop files of the store cached before the import
+    if let Some(url) = store_url(&name) {
+        plugin.cache.invalidate(&url).await;
+    }
+
+    Ok(PipelineData::Value(
+        summary_record(&name, &path, summary, call_span),
+        None,
+    ))
+}
@@ -0,0 +1,83 @@
+use std::path::Path;
+
+use nu_plugin::PluginCommand;
+use nu_protocol::{Category, PipelineData, ShellError, Signature, Span, Type, Value, record};
+
+use url::Url;
+
+use crate::{CloudPlugin, providers::DEFAULT_MEMORY_STORE};
+
+mod archive;
+pub mod export;
+pub mod import;
+pub mod reset;
+
+pub struct MemoryStub;
+
+impl PluginCommand for MemoryStub {
+    type Plugin = CloudPlugin;
+
+    fn name(&self) -> &str {
+        "cloud memory"
+    }
+
+    fn signature(&self) -> nu_protocol::Signature {
+        Signature::build("cloud memory")
+            .category(Category::FileSystem)
+            .input_output_types(vec![(Type::Nothing, Type::String)])
+    }
+
+    fn description(&self) -> &str {
+        "Manage the in-memory stores of memory: urls"
+    }
+
+    fn extra_description(&self) -> &str {
+        "`memory:/` urls use the default store, `memory://<name>/` urls use a separate named store. \
+        Memory stores keep their files until they are reset, even when the cache is cleared."
+    }
+
+    fn run(
+        &self,
+        _plugin: &Self::Plugin,
+        engine: &nu_plugin::EngineInterface,
+        call: &nu_plugin::EvaluatedCall,
+        _input: nu_protocol::PipelineData,
+    ) -> Result<nu_protocol::PipelineData, nu_protocol::LabeledError> {
+        Ok(PipelineData::Value(
+            Value::string(engine.get_help()?, call.head),
+            None,
+        ))
+    }
+}
+
+/// Returns the url prefix of the files of the memory store called name, or [`None`] if name
+/// can't be the host of a url, in which case none of its files can be cached
+fn store_url(name: &str) -> Option<Url> {
+    let url = match name {
+        DEFAULT_MEMORY_STORE => "memory:/".to_string(),
+        name => format!("memory://{name}/"),
+    };
+    Url::parse(&url).ok()
+}
+
+fn archive_error(action: &str, error: archive::ArchiveError, span: Span) -> Box<ShellError> {
+    Box::new(ShellError::GenericError {
+        error: format!("{action}: {error}"),
+        msg: "".into(),
+        span: Some(span),
+        help: None,
+        inner: vec![],
+    })
+}
+
+fn summary_record(store: &str, path: &Path, summary: archive::ArchiveSummary, span: Span) -> Value {
+    Value::record(
+        record!(
+            "store" => Value::string(store, span),
+            "file" => Value::string(path.to_string_lossy(), span),
+            "files" => Value::int(summary.files as i64, span),
+            "size" => Value::filesize(summary.size as i64, span),
+        ),
+        span,
+    )
+}
@@ -0,0 +1,114 @@
+use futures::TryStreamExt;
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
+use nu_protocol::{
+    Category, Example, LabeledError, PipelineData, ShellError, Signature, SyntaxShape, Type, Value,
+    record,
+};
+use object_store::{ObjectMeta, ObjectStore};
+
+use crate::CloudPlugin;
+
+use super::store_url;
+
+pub struct MemoryReset;
+
+impl PluginCommand for MemoryReset {
+    type Plugin = CloudPlugin;
+
+    fn name(&self) -> &str {
+        "cloud memory reset"
+    }
+
+    fn signature(&self) -> nu_protocol::Signature {
+        Signature::build("cloud memory reset")
+            .input_output_types(vec![(Type::Nothing, Type::table())])
+            .optional(
+                "name",
+                SyntaxShape::String,
+                "The memory store to reset, i.e. scratch for memory://scratch/. Resets every memory store if omitted.",
+            )
+            .category(Category::FileSystem)
+    }
+
+    fn description(&self) -> &str {
+        "Delete every file of a memory store, or of all memory stores."
+    }
+
+    fn extra_description(&self) -> &str {
+        "The store of memory:/ urls is called default. Returns the name, number of files and size of each reset store."
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![
+            Example {
+                description: "Reset the memory://scratch/ store.",
+                example: "cloud memory reset scratch",
+                result: None,
+            },
+            Example {
+                description: "Reset the store of memory:/ urls.",
+                example: "cloud memory reset default",
+                result: None,
+            },
+            Example {
+                description: "Reset every memory store.",
+                example: "cloud memory reset",
+                result: None,
+            },
+        ]
+    }
+
+    fn run(
+        &self,
+        plugin: &Self::Plugin,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        _input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        plugin
+            .rt
+            .block_on(command(plugin, engine, call))
+            .map_err(|e| LabeledError::from(*e))
+    }
+}
+
+async fn command(
+    plugin: &CloudPlugin,
+    engine: &EngineInterface,
+    call: &EvaluatedCall,
+) -> Result<PipelineData, Box<ShellError>> {
+    let call_span = call.head;
+    let name: Option<String> = call.opt(0)?;
+
+    let removed = plugin.cache.memory.reset(name.as_deref()).await;
+    plugin.cache.enable_gc_if_unused(engine).await?;
+
+    let mut rows = vec![];
+    for (name, store) in removed {
+        // Cached files would otherwise still be served from the removed store
+        if let Some(url) = store_url(&name) {
+            plugin.cache.invalidate(&url).await;
+        }
+        let files: Vec<ObjectMeta> =
+            store
+                .list(None)
+                .try_collect()
+                .await
+                .map_err(|e| ShellError::GenericError {
+                    error: format!("Could not list memory store {name}: {e}"),
+                    msg: "".into(),
+                    span: Some(call_span),
+                    help: None,
+                    inner: vec![],
+                })?;
+        rows.push(Value::record(
+            record!(
+                "name" => Value::string(name, call_span),
+                "files" => Value::int(files.len() as i64, call_span),
+                "size" => Value::filesize(files.iter().map(|f| f.size as i64).sum::<i64>(), call_span),
+            ),
+            call_span,
+        ));
+    }
+    Ok(PipelineData::Value(Value::list(rows, call_span), None))
+}
@@ -1,29 +1,486 @@
-use crate::CloudPlugin;
+use std::{io::IsTerminal, path::PathBuf, str::FromStr};
 
+use crate::{
+    CloudPlugin,
+    config::{PluginConfig, RESERVED_SCHEMES},
+    providers::{ClientSettings, NuObjectStore, object_url},
+};
+use nu_plugin::{EngineInterface, EvaluatedCall};
+use nu_protocol::{NuGlob, PipelineData, ShellError, Signature, Span, Spanned, SyntaxShape, Value};
+use object_store::path::Path;
+use url::Url;
+
+mod cache;
 mod clear;
+mod complete;
+mod doctor;
+mod glob;
 mod ls;
+mod memory;
+mod multipart;
 mod open;
+mod presign;
+mod restore;
 mod rm;
 mod save;
 mod stub;
+mod whoami;
 
 pub fn commands() -> Vec<Box<dyn nu_plugin::PluginCommand<Plugin = CloudPlugin>>> {
     vec![
+        Box::new(cache::CacheStub),
+        Box::new(cache::invalidate::CacheInvalidate),
+        Box::new(cache::list::CacheList),
+        Box::new(cache::stats::CacheStats),
         Box::new(clear::Clear),
+        Box::new(doctor::Doctor),
         Box::new(ls::Ls),
+        Box::new(memory::MemoryStub),
+        Box::new(memory::export::MemoryExport),
+        Box::new(memory::import::MemoryImport),
+        Box::new(memory::reset::MemoryReset),
         Box::new(open::Open),
+        Box::new(presign::Presign),
+        Box::new(restore::Restore),
         Box::new(rm::Remove),
         Box::new(save::Save),
         Box::new(stub::Stub),
+        Box::new(whoami::Whoami),
     ]
 }
 
+/// Parses a url argument into a [`Url`]. Local paths, such as `./data.csv`, `~/data.csv`
+/// or `file:data.csv`, are resolved against the current directory of the shell.
+pub(crate) fn parse_url_arg(
+    engine: &EngineInterface,
+    url: &Spanned<String>,
+    call_span: Span,
+) -> Result<Spanned<Url>, Box<ShellError>> {
+    if let Some(item) = local_url(engine, url, call_span)? {
+        return Ok(Spanned {
+            item,
+            span: url.span,
+        });
+    }
+    if let Some(segment) = dot_segment(&url.item) {
+        return Err(Box::new(ShellError::IncorrectValue {
+            msg: format!(
+                "Url contains a `{segment}` segment, which would be removed from the key. Use --literal-key to keep it"
+            ),
+            val_span: url.span,
+            call_span,
+        }));
+    }
+    Ok(Spanned {
+        item: Url::from_str(&url.item).map_err(|e| ShellError::IncorrectValue {
+            msg: format!("Invalid Url: {e}"),
+            val_span: url.span,
+            call_span,
+        })?,
+        span: url.span,
+    })
+}
+
+/// Returns the `file://` url of a local path argument, or [`None`] if it is a url
+fn local_url(
+    engine: &EngineInterface,
+    url: &Spanned<String>,
+    call_span: Span,
+) -> Result<Option<Url>, Box<ShellError>> {
+    let span = url.span;
+    let path = match url.item.strip_prefix("file:") {
+        Some(path) if !path.starts_with('/') => path,
+        _ if has_scheme(&url.item) => return Ok(None),
+        _ => url.item.as_str(),
+    };
+    // Keep directories as prefixes, e.g. for `cloud ls .`
+    let is_dir = path.is_empty()
+        || path.ends_with(['/', std::path::MAIN_SEPARATOR])
+        || [".", "..", "~"]
+            .iter()
+            .any(|dir| path == *dir || path.ends_with(&format!("/{dir}")));
+    let path = local_path(
+        engine,
+        &Spanned {
+            item: PathBuf::from(path),
+            span,
+        },
+    )?;
+    let url = if is_dir {
+        Url::from_directory_path(&path)
+    } else {
+        Url::from_file_path(&path)
+    };
+    url.map(Some).map_err(|_| {
+        Box::new(ShellError::IncorrectValue {
+            msg: format!("Invalid local path {}", path.display()),
+            val_span: span,
+            call_span,
+        })
+    })
+}
+
+/// Returns the first `.` or `..` segment of the path of a url, which parsing the url
+/// would resolve. `file:` urls are local paths, where they are resolved as usual.
+fn dot_segment(url: &str) -> Option<&str> {
+    let (root, key) = split_literal(url)?;
+    if root.starts_with("file:") {
+        return None;
+    }
+    let key = key.split(['?', '#']).next().unwrap_or_default();
+    key.split('/').find(|segment| {
+        let segment = segment.to_ascii_lowercase().replace("%2e", ".");
+        segment == "." || segment == ".."
+    })
+}
+
+/// Splits a url into the url of the root of its store and the key, exactly as written,
+/// i.e. `s3://bucket/` and `a/b` for `s3://bucket/a/b`. Returns [`None`] for local paths.
+fn split_literal(url: &str) -> Option<(String, &str)> {
+    let (scheme, rest) = url.split_once(':').filter(|_| has_scheme(url))?;
+    // The key follows the authority of `scheme://authority/key`, or `scheme:/key`
+    let (root, key) = match rest.strip_prefix("//") {
+        Some(rest) => {
+            let end = rest.find('/').unwrap_or(rest.len());
+            (format!("{scheme}://{}/", &rest[..end]), &rest[end..])
+        }
+        None => (format!("{scheme}:/"), rest),
+    };
+    Some((root, key.strip_prefix('/').unwrap_or(key)))
+}
+
+/// Returns the url of the object whose key is the path of url exactly as written, for
+/// `--literal-key`. Unlike parsing a url, this keeps `?`, `#`, `%` and dot segments in the
+/// key. Local paths are returned as is.
+pub(crate) fn literal_url(
+    url: &Spanned<String>,
+    call_span: Span,
+) -> Result<Spanned<String>, Box<ShellError>> {
+    let Some((root, key)) = split_literal(&url.item) else {
+        return Ok(url.clone());
+    };
+
+    let path = Path::parse(key).map_err(|e| ShellError::GenericError {
+        error: format!("Unsupported key {key}"),
+        msg: e.to_string(),
+        span: Some(url.span),
+        help: Some(
+            "Keys with empty segments, `.` or `..` segments or control characters can only be \
+            accessed in s3 buckets, with `cloud open`, `cloud save` and `cloud rm`"
+                .into(),
+        ),
+        inner: vec![],
+    })?;
+    let root = Url::from_str(&root).map_err(|e| ShellError::IncorrectValue {
+        msg: format!("Invalid Url: {e}"),
+        val_span: url.span,
+        call_span,
+    })?;
+    let mut item = object_url(&root, &path).to_string();
+    // Keep prefixes such as `s3://bucket/dir/` listable
+    if key.ends_with('/') && !path.is_root() {
+        item.push('/');
+    }
+    Ok(Spanned {
+        item,
+        span: url.span,
+    })
+}
+
+/// An s3 object addressed with `--literal-key` whose key object_store can't represent
+/// exactly, such as `a//b`, `a/../b` or `dir/`. It is accessed with the S3 sdk instead.
+pub(crate) struct RawKey {
+    /// The url of the bucket, i.e. `s3://bucket/`
+    pub bucket_url: Spanned<String>,
+    pub key: String,
+}
+
+impl RawKey {
+    /// Returns the store of the bucket
+    pub(crate) async fn object_store(
+        &self,
+        plugin: &CloudPlugin,
+        engine: &EngineInterface,
+        settings: &ClientSettings,
+        call_span: Span,
+    ) -> Result<NuObjectStore, Box<ShellError>> {
+        let url = parse_url_arg(engine, &self.bucket_url, call_span)?;
+        let (object_store, _) = plugin.parse_url(engine, &url, settings, call_span).await?;
+        Ok(object_store)
+    }
+}
+
+/// Returns the bucket and exact key of an s3 url passed with `--literal-key`, if object_store
+/// can't represent the key. Other urls are opened with [`literal_url`].
+pub(crate) fn raw_s3_key(url: &Spanned<String>) -> Option<RawKey> {
+    let (root, key) = split_literal(&url.item)?;
+    let s3 = root.starts_with("s3:") || root.starts_with("s3a:");
+    if !s3 || Path::parse(key).is_ok_and(|path| path.as_ref() == key) {
+        return None;
+    }
+    Some(RawKey {
+        bucket_url: Spanned {
+            item: root,
+            span: url.span,
+        },
+        key: key.to_string(),
+    })
+}
+
+/// Replaces the alias a url argument starts with by its url, see
+/// [`Aliases`](crate::config::Aliases)
+pub(crate) fn resolve_alias(
+    engine: &EngineInterface,
+    url: Spanned<String>,
+) -> Result<Spanned<String>, Box<ShellError>> {
+    let is_alias = url.item.split_once(':').is_some_and(|(scheme, _)| {
+        has_scheme(&url.item) && !RESERVED_SCHEMES.contains(&scheme.to_lowercase().as_str())
+    });
+    if !is_alias {
+        return Ok(url);
+    }
+    let item = PluginConfig::load(engine)?.aliases.resolve(&url.item);
+    Ok(Spanned {
+        item: item.unwrap_or(url.item),
+        span: url.span,
+    })
+}
+
+/// [`resolve_alias`] for url arguments that may be glob patterns
+pub(crate) fn resolve_glob_alias(
+    engine: &EngineInterface,
+    url: Spanned<NuGlob>,
+) -> Result<Spanned<NuGlob>, Box<ShellError>> {
+    let (item, expand) = match url.item {
+        NuGlob::Expand(item) => (item, true),
+        NuGlob::DoNotExpand(item) => (item, false),
+    };
+    let resolved = resolve_alias(
+        engine,
+        Spanned {
+            item,
+            span: url.span,
+        },
+    )?;
+    Ok(Spanned {
+        item: match expand {
+            true => NuGlob::Expand(resolved.item),
+            false => NuGlob::DoNotExpand(resolved.item),
+        },
+        span: url.span,
+    })
+}
+
+/// Adds the --literal-key switch, see [`literal_url`]
+pub(crate) fn literal_key_flag(signature: Signature) -> Signature {
+    signature.switch(
+        "literal-key",
+        "use the path of the url as the exact object key, without percent-decoding or globbing",
+        None,
+    )
+}
+
+/// Returns the url argument as is, or its [`literal_url`] with --literal-key
+pub(crate) fn apply_literal_key(
+    call: &EvaluatedCall,
+    url: Spanned<String>,
+) -> Result<Spanned<String>, Box<ShellError>> {
+    match call.has_flag("literal-key")? {
+        true => literal_url(&url, call.head),
+        false => Ok(url),
+    }
+}
+
+/// Returns true if the argument starts with a url scheme such as `s3:`.
+/// Single letters are not schemes so that windows paths like `C:\data` are local.
+pub(crate) fn has_scheme(url: &str) -> bool {
+    url.split_once(':').is_some_and(|(scheme, _)| {
+        scheme.len() > 1
+            && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+            && scheme
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'))
+    })
+}
+
+/// Resolves a path argument relative to the current directory of the shell
+pub(crate) fn local_path(
+    engine: &EngineInterface,
+    path: &Spanned<PathBuf>,
+) -> Result<PathBuf, Box<ShellError>> {
+    let expanded = nu_path::expand_tilde(&path.item);
+    if expanded.is_absolute() {
+        return Ok(expanded);
+    }
+    let cwd = engine.get_current_dir()?;
+    Ok(nu_path::expand_path_with(expanded, cwd, true))
+}
+
+/// Adds the flags overriding the http client settings of the plugin config
+pub(crate) fn client_flags(signature: Signature) -> Signature {
+    signature
+        .named(
+            "retries",
+            SyntaxShape::Int,
+            "maximum number of retries of a failed request, 0 disables retries",
+            None,
+        )
+        .named(
+            "retry-timeout",
+            SyntaxShape::Duration,
+            "stop retrying a request this long after the first attempt",
+            None,
+        )
+        .named(
+            "connect-timeout",
+            SyntaxShape::Duration,
+            "timeout for connecting to the server",
+            None,
+        )
+        .named(
+            "timeout",
+            SyntaxShape::Duration,
+            "timeout of each request, including reading the response",
+            None,
+        )
+        .named(
+            "proxy",
+            SyntaxShape::String,
+            "url of the proxy to send requests through",
+            None,
+        )
+        .named(
+            "no-proxy",
+            SyntaxShape::List(Box::new(SyntaxShape::String)),
+            "hosts and domains to access without the proxy",
+            None,
+        )
+        .named(
+            "ca-bundle",
+            SyntaxShape::Filepath,
+            "PEM file with additional root certificates to trust",
+            None,
+        )
+        .named(
+            "http2",
+            SyntaxShape::String,
+            "whether to use HTTP/2: never (the default), allow or only",
+            None,
+        )
+}
+
+/// Returns the http client settings of the plugin config, overridden by the flags added
+/// by [`client_flags`]
+pub(crate) fn client_settings(
+    engine: &EngineInterface,
+    call: &EvaluatedCall,
+) -> Result<ClientSettings, Box<ShellError>> {
+    let mut settings = PluginConfig::load(engine)?.client;
+    for name in crate::providers::CLIENT_SETTINGS {
+        if let Some(value) = call.get_flag_value(&name.replace('_', "-")) {
+            settings.set(name, &value)?;
+        }
+    }
+    Ok(settings)
+}
+
+/// Returns whether transfer progress should be reported, based on the --progress and
+/// --quiet flags. Without either, progress is reported when stderr is a terminal.
+pub(crate) fn progress_enabled(call: &EvaluatedCall) -> Result<bool, Box<ShellError>> {
+    match (call.has_flag("progress")?, call.has_flag("quiet")?) {
+        (true, true) => Err(Box::new(ShellError::IncompatibleParameters {
+            left_message: "can't use --progress".into(),
+            left_span: call.get_flag_span("progress").unwrap_or(call.head),
+            right_message: "together with --quiet".into(),
+            right_span: call.get_flag_span("quiet").unwrap_or(call.head),
+        })),
+        (true, false) => Ok(true),
+        (false, true) => Ok(false),
+        (false, false) => Ok(std::io::stderr().is_terminal()),
+    }
+}
+
+/// Collects urls from pipeline input. Strings, records with a `url` column such as the
+/// rows returned by `cloud ls`, and lists of either are accepted.
+pub(crate) fn input_urls(
+    engine: &EngineInterface,
+    input: PipelineData,
+    call_span: Span,
+) -> Result<Vec<Spanned<String>>, Box<ShellError>> {
+    match input {
+        PipelineData::Empty => Ok(vec![]),
+        input => value_urls(input.into_value(call_span)?, call_span)?
+            .into_iter()
+            .map(|url| resolve_alias(engine, url))
+            .collect(),
+    }
+}
+
+fn value_urls(value: Value, call_span: Span) -> Result<Vec<Spanned<String>>, Box<ShellError>> {
+    let span = value.span();
+    match value {
+        Value::Nothing { .. } => Ok(vec![]),
+        Value::String { val, .. } => Ok(vec![Spanned { item: val, span }]),
+        Value::Record { val, .. } => match val.get("url") {
+            Some(url) => Ok(vec![Spanned {
+                item: url.coerce_str()?.to_string(),
+                span: url.span(),
+            }]),
+            None => Err(Box::new(ShellError::CantFindColumn {
+                col_name: "url".into(),
+                span: Some(call_span),
+                src_span: span,
+            })),
+        },
+        Value::List { vals, .. } => {
+            let mut urls = vec![];
+            for val in vals {
+                urls.extend(value_urls(val, call_span)?);
+            }
+            Ok(urls)
+        }
+        Value::Error { error, .. } => Err(error),
+        other => Err(Box::new(ShellError::OnlySupportsThisInputType {
+            exp_input_type: "string, record or table".into(),
+            wrong_type: other.get_type().to_string(),
+            dst_span: call_span,
+            src_span: span,
+        })),
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::CloudPlugin;
-    use nu_command::{FromCsv, Select, ToCsv};
+    use super::{complete, has_scheme, raw_s3_key};
+    use crate::{
+        CloudPlugin,
+        config::Aliases,
+        error::CloudError,
+        providers::{ClientSettings, DEFAULT_MEMORY_STORE, Provider, S3HttpClient},
+    };
+    use aws_smithy_runtime_api::client::{http::HttpConnector, orchestrator::HttpRequest};
+    use aws_smithy_types::body::SdkBody;
+    use nu_command::{Each, FromCsv, Get, Length, Select, ToCsv};
+    use nu_plugin::DynamicCompletionCall;
     use nu_plugin_test_support::PluginTest;
-    use nu_protocol::{PipelineData, Span, Value, record};
+    use nu_protocol::{
+        DynamicSuggestion, Spanned, Type,
+        ast::{Call, Expr, Expression},
+    };
+    use nu_protocol::{
+        LabeledError, PipelineData, PipelineMetadata, ShellError, Span, Value, record,
+    };
+    use object_store::{
+        Attribute, GetOptions, ObjectStore, ObjectStoreExt, memory::InMemory, path::Path,
+    };
+    use std::{
+        io::{BufRead, BufReader, Write},
+        net::{TcpListener, TcpStream},
+        sync::Arc,
+        thread::{self, JoinHandle},
+        time::{Duration, Instant},
+    };
+    use url::Url;
 
     #[test]
     fn test_save_open() -> Result<(), Box<dyn std::error::Error>> {
@@ -61,6 +518,151 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_save_attributes() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = Arc::new(CloudPlugin::default());
+        let mut plugin_test = PluginTest::new("polars", plugin.clone())?;
+        let attributes = |key: &str| {
+            plugin.rt.block_on(async {
+                let store = plugin.cache.memory.lock().await[DEFAULT_MEMORY_STORE].clone();
+                let result = store
+                    .get_opts(&Path::from(key), GetOptions::default())
+                    .await;
+                result.map(|result| result.attributes)
+            })
+        };
+
+        let _ = plugin_test.eval(
+            "'hello' | cloud save --content-type text/plain --cache-control no-cache --metadata {owner: me} memory:/foo.txt",
+        )?;
+        let attributes_of_foo = attributes("foo.txt")?;
+        assert_eq!(
+            attributes_of_foo.get(&Attribute::ContentType),
+            Some(&"text/plain".into())
+        );
+        assert_eq!(
+            attributes_of_foo.get(&Attribute::CacheControl),
+            Some(&"no-cache".into())
+        );
+        assert_eq!(
+            attributes_of_foo.get(&Attribute::Metadata("owner".into())),
+            Some(&"me".into())
+        );
+
+        // Without --content-type it is inferred from the extension, then the pipeline metadata
+        let _ = plugin_test.eval("'<p>hello</p>' | cloud save --raw memory:/page.html")?;
+        assert_eq!(
+            attributes("page.html")?.get(&Attribute::ContentType),
+            Some(&"text/html".into())
+        );
+        let metadata = PipelineMetadata {
+            content_type: Some("application/x-custom".into()),
+            ..PipelineMetadata::default()
+        };
+        let _ = plugin_test.eval_with(
+            "cloud save memory:/blob",
+            PipelineData::Value(Value::test_string("hello"), Some(metadata)),
+        )?;
+        assert_eq!(
+            attributes("blob")?.get(&Attribute::ContentType),
+            Some(&"application/x-custom".into())
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_save_conditional() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("polars", plugin.into())?;
+        let _ = plugin_test.add_decl(Box::new(Get))?;
+        let _ = plugin_test.eval("'first' | cloud save --no-clobber memory:/foo.txt")?;
+        let mut code = |command: &str| {
+            let Err(error) = plugin_test.eval(command) else {
+                panic!("{command} should fail");
+            };
+            LabeledError::from_diagnostic(&error).code
+        };
+        for flag in ["--no-clobber", "--if-not-exists"] {
+            assert_eq!(
+                code(&format!("'second' | cloud save {flag} memory:/foo.txt")).as_deref(),
+                Some("nu_plugin_cloud::already_exists")
+            );
+        }
+        assert_eq!(
+            code("'second' | cloud save --if-match wrong memory:/foo.txt").as_deref(),
+            Some("nu_plugin_cloud::precondition_failed")
+        );
+        assert_eq!(
+            code("'second' | cloud save --if-not-exists --if-match wrong memory:/foo.txt")
+                .as_deref(),
+            Some("nu::shell::incompatible_parameters")
+        );
+        let result = plugin_test.eval(
+            "let etag = cloud ls memory:/foo.txt | get 0.etag; 'third' | cloud save --if-match $etag memory:/foo.txt; cloud open memory:/foo.txt",
+        )?;
+        let value = result.into_value(Span::test_data())?;
+        assert_eq!(value, Value::test_string("third"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_save_result() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("polars", plugin.into())?;
+        let _ = plugin_test.add_decl(Box::new(Select))?;
+        let result = plugin_test
+            .eval("'hello' | cloud save --result memory:/foo.txt | select url size multipart")?;
+        let value = result.into_value(Span::test_data())?;
+        assert_eq!(
+            value,
+            Value::test_record(record!(
+                "url" => Value::test_string("memory:/foo.txt"),
+                "size" => Value::test_filesize(5),
+                "multipart" => Value::test_bool(false),
+            ))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_save_multipart() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("polars", plugin.into())?;
+        let _ = plugin_test.add_decl(Box::new(Get))?;
+        let _ = plugin_test.add_decl(Box::new(Each))?;
+        assert!(
+            plugin_test
+                .eval("['a' 'b'] | cloud save --raw --part-size 1KiB memory:/foo.txt")
+                .is_err()
+        );
+        let result = plugin_test.eval(
+            "['a' 'b'] | each { $in } | cloud save --raw --result --part-size 8MiB --concurrency 2 memory:/foo.txt | get multipart",
+        )?;
+        let value = result.into_value(Span::test_data())?;
+        assert_eq!(value, Value::test_bool(true));
+        let result = plugin_test.eval("cloud open memory:/foo.txt")?;
+        let value = result.into_value(Span::test_data())?;
+        assert_eq!(value, Value::test_string("ab"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_progress_flags() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("polars", plugin.into())?;
+        let result = plugin_test.eval(
+            "'hello' | cloud save --quiet memory:/foo.txt; cloud open --progress memory:/foo.txt",
+        )?;
+        let value = result.into_value(Span::test_data())?;
+        assert_eq!(value, Value::test_string("hello"));
+        assert!(
+            plugin_test
+                .eval("cloud open --progress --quiet memory:/foo.txt")
+                .is_err()
+        );
+        Ok(())
+    }
+
     #[test]
     fn test_save_raw_open() -> Result<(), Box<dyn std::error::Error>> {
         let plugin = CloudPlugin::default();
@@ -102,4 +704,974 @@ mod tests {
         );
         Ok(())
     }
+
+    #[test]
+    fn test_open_not_found() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("polars", plugin.into())?;
+        let Err(error) = plugin_test.eval("cloud open memory:/missing.txt") else {
+            panic!("opening a missing file should fail");
+        };
+        let error = LabeledError::from_diagnostic(&error);
+        assert_eq!(error.code.as_deref(), Some("nu_plugin_cloud::not_found"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_help() -> Result<(), Box<dyn std::error::Error>> {
+        // The hint depends on the storage the error came from
+        let help = |provider| {
+            let error = object_store::Error::PermissionDenied {
+                path: "foo.txt".into(),
+                source: "denied".into(),
+            };
+            let error = ShellError::from(CloudError::new(
+                "Could not read file",
+                error,
+                provider,
+                Span::test_data(),
+            ));
+            let error = LabeledError::from_diagnostic(&error);
+            assert_eq!(
+                error.code.as_deref(),
+                Some("nu_plugin_cloud::permission_denied")
+            );
+            error.help.unwrap_or_default()
+        };
+        assert!(help(Provider::AmazonS3).contains("AWS_PROFILE"));
+        assert!(!help(Provider::Local).contains("AWS"));
+        assert!(!help(Provider::Memory).contains("AWS"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_presign_unsupported() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("polars", plugin.into())?;
+        let Err(error) = plugin_test.eval("cloud presign memory:/foo.csv") else {
+            panic!("memory urls can't be presigned");
+        };
+        assert_eq!(
+            LabeledError::from_diagnostic(&error).msg,
+            "Cannot presign memory urls"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_presign_expires() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("polars", plugin.into())?;
+        let Err(error) = plugin_test.eval("cloud presign --expires 8day s3://mybucket/file.txt")
+        else {
+            panic!("an expiry of more than 7 days should be rejected");
+        };
+        let error = LabeledError::from_diagnostic(&error);
+        assert_eq!(
+            error.msg,
+            "Invalid expiry: signed urls are valid for at most 7 days"
+        );
+        assert_eq!(error.labels[0].text, "longer than 7 days");
+        Ok(())
+    }
+
+    #[test]
+    fn test_versions_unsupported() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("polars", plugin.into())?;
+        let _ = plugin_test.eval("'hello' | cloud save memory:/foo.txt")?;
+        assert!(plugin_test.eval("cloud ls --versions memory:/").is_err());
+        assert!(
+            plugin_test
+                .eval("cloud open --version 1 memory:/foo.txt")
+                .is_err()
+        );
+        assert!(
+            plugin_test
+                .eval("cloud restore --version 1 memory:/foo.txt")
+                .is_err()
+        );
+        for flag in ["--start-after foo.txt", "--recursive", "--max-depth 2"] {
+            let Err(error) = plugin_test.eval(&format!("cloud ls --versions {flag} memory:/"))
+            else {
+                panic!("{flag} should be rejected with --versions");
+            };
+            assert_eq!(
+                LabeledError::from_diagnostic(&error).code.as_deref(),
+                Some("nu::shell::incompatible_parameters")
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_whoami() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("polars", plugin.into())?;
+        let result = plugin_test.eval("cloud whoami memory:/")?;
+        let value = result.into_value(Span::test_data())?;
+        let record = value.as_record()?;
+        assert_eq!(record.get("provider"), Some(&Value::test_string("memory")));
+        assert!(record.get("access_key_id").is_some_and(Value::is_nothing));
+
+        let result = plugin_test.eval("cloud doctor memory:/")?;
+        let value = result.into_value(Span::test_data())?;
+        assert_eq!(value.as_list()?.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_client_flags() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("polars", plugin.into())?;
+        let _ = plugin_test.eval("'hello' | cloud save --retries 2 memory:/foo.txt")?;
+        let result = plugin_test.eval(
+            "cloud open --timeout 30sec --connect-timeout 5sec --http2 allow --no-proxy [localhost] memory:/foo.txt",
+        )?;
+        assert_eq!(
+            result.into_value(Span::test_data())?,
+            Value::test_string("hello")
+        );
+        assert!(plugin_test.eval("cloud ls --retries -1 memory:/").is_err());
+        assert!(
+            plugin_test
+                .eval("cloud ls --http2 sometimes memory:/")
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_idle_expiry() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = Arc::new(CloudPlugin::default());
+        let mut plugin_test = PluginTest::new("polars", plugin.clone())?;
+        let cached_files =
+            |plugin_test: &mut PluginTest| -> Result<Option<Value>, Box<dyn std::error::Error>> {
+                let stats = plugin_test.eval("cloud cache stats")?;
+                Ok(stats
+                    .into_value(Span::test_data())?
+                    .as_record()?
+                    .get("files")
+                    .cloned())
+            };
+
+        let _ = plugin_test.eval("'hello' | cloud save memory:/foo.txt")?;
+        let _ = plugin_test.eval("'world' | cloud save memory://scratch/bar.txt")?;
+        let _ = plugin_test.eval("cloud open memory:/foo.txt")?;
+        assert_eq!(cached_files(&mut plugin_test)?, Some(Value::test_int(1)));
+
+        // The expiry runs as if it was later instead of waiting for the idle timeout
+        let idle_timeout = Duration::from_secs(60);
+        let now = Instant::now();
+        assert!(
+            !plugin
+                .rt
+                .block_on(plugin.cache.expire_if_idle(idle_timeout, now))
+        );
+        assert!(
+            plugin.rt.block_on(
+                plugin
+                    .cache
+                    .expire_if_idle(idle_timeout, now + idle_timeout)
+            )
+        );
+        assert_eq!(cached_files(&mut plugin_test)?, Some(Value::test_int(0)));
+
+        // Memory stores hold data and survive the expiry
+        for (url, data) in [
+            ("memory:/foo.txt", "hello"),
+            ("memory://scratch/bar.txt", "world"),
+        ] {
+            let result = plugin_test.eval(&format!("cloud open {url}"))?;
+            assert_eq!(
+                result.into_value(Span::test_data())?,
+                Value::test_string(data)
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_cache_commands() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("polars", plugin.into())?;
+        let _ = plugin_test.eval("'hello' | cloud save memory:/foo.txt")?;
+        let _ = plugin_test.eval("cloud open memory:/foo.txt")?;
+        let _ = plugin_test.eval("cloud open memory:/foo.txt")?;
+
+        let stats = plugin_test.eval("cloud cache stats")?;
+        let stats = stats.into_value(Span::test_data())?;
+        let stats = stats.as_record()?;
+        assert_eq!(stats.get("files"), Some(&Value::test_int(1)));
+        assert_eq!(stats.get("hits"), Some(&Value::test_int(1)));
+        assert_eq!(stats.get("misses"), Some(&Value::test_int(1)));
+
+        let list = plugin_test.eval("cloud cache list")?;
+        let list = list.into_value(Span::test_data())?;
+        let files = list
+            .as_record()?
+            .get("files")
+            .ok_or("no files")?
+            .as_list()?;
+        assert_eq!(files.len(), 1);
+        assert_eq!(
+            files[0].as_record()?.get("url"),
+            Some(&Value::test_string("memory:/foo.txt"))
+        );
+
+        let removed = plugin_test.eval("cloud cache invalidate memory:/")?;
+        assert_eq!(removed.into_value(Span::test_data())?.as_list()?.len(), 1);
+
+        let list = plugin_test.eval("cloud cache list")?;
+        let list = list.into_value(Span::test_data())?;
+        assert_eq!(
+            list.as_record()?.get("files"),
+            Some(&Value::test_list(vec![]))
+        );
+        let result = plugin_test.eval("cloud open memory:/foo.txt")?;
+        assert_eq!(
+            result.into_value(Span::test_data())?,
+            Value::test_string("hello")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_cache_invalidate() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("polars", plugin.into())?;
+        let _ = plugin_test.add_decl(Box::new(Get))?;
+        for url in [
+            "memory:/logs/a.txt",
+            "memory:/logs2.txt",
+            "memory://scratch/logs/b.txt",
+        ] {
+            let _ = plugin_test.eval(&format!("'hello' | cloud save {url}; cloud open {url}"))?;
+        }
+
+        // Prefixes match whole path segments, in the store of the prefix only
+        let mut invalidate = |prefix: &str| -> Result<Value, Box<dyn std::error::Error>> {
+            let removed =
+                plugin_test.eval(&format!("cloud cache invalidate {prefix} | get url"))?;
+            Ok(removed.into_value(Span::test_data())?)
+        };
+        assert_eq!(
+            invalidate("memory:/logs")?,
+            Value::test_list(vec![Value::test_string("memory:/logs/a.txt")])
+        );
+        assert_eq!(
+            invalidate("memory:/")?,
+            Value::test_list(vec![Value::test_string("memory:/logs2.txt")])
+        );
+        assert_eq!(
+            invalidate("memory://scratch/logs/")?,
+            Value::test_list(vec![Value::test_string("memory://scratch/logs/b.txt")])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_memory_stores() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("polars", plugin.into())?;
+        let _ = plugin_test.add_decl(Box::new(Select))?;
+        let _ = plugin_test.eval("'hello' | cloud save memory:/foo.txt")?;
+        let _ = plugin_test.eval("'scratch' | cloud save memory://scratch/bar.txt")?;
+        let _ = plugin_test.eval("cloud cache-clear")?;
+
+        // Named stores are separate and survive cache-clear
+        let result = plugin_test.eval("cloud ls memory://scratch/ | select url")?;
+        assert_eq!(
+            result.into_value(Span::test_data())?,
+            Value::test_list(vec![Value::test_record(record!(
+                "url" => Value::test_string("memory://scratch/bar.txt")
+            ))])
+        );
+        let result = plugin_test.eval("cloud open memory:/foo.txt")?;
+        assert_eq!(
+            result.into_value(Span::test_data())?,
+            Value::test_string("hello")
+        );
+
+        // Cache the file, so that the reset has to drop it from the cache
+        let result = plugin_test.eval("cloud open memory://scratch/bar.txt")?;
+        assert_eq!(
+            result.into_value(Span::test_data())?,
+            Value::test_string("scratch")
+        );
+        let result = plugin_test.eval("cloud memory reset scratch | select name files")?;
+        assert_eq!(
+            result.into_value(Span::test_data())?,
+            Value::test_list(vec![Value::test_record(record!(
+                "name" => Value::test_string("scratch"),
+                "files" => Value::test_int(1),
+            ))])
+        );
+        assert!(
+            plugin_test
+                .eval("cloud open memory://scratch/bar.txt")
+                .is_err()
+        );
+        assert!(plugin_test.eval("cloud open memory:/foo.txt").is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn test_memory_export_import() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("polars", plugin.into())?;
+        let dir = std::env::temp_dir().join(format!("nu_plugin_cloud_{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let first = dir.join("first.tar");
+        let second = dir.join("second.tar");
+
+        let _ = plugin_test
+            .eval("'hello' | cloud save --metadata {owner: me} memory://fixtures/dir/foo.txt")?;
+        let _ = plugin_test.eval(&format!(
+            "cloud memory export --store fixtures {}",
+            first.display()
+        ))?;
+        let _ = plugin_test.eval("cloud memory reset fixtures")?;
+        let result = plugin_test.eval(&format!(
+            "cloud memory import --store fixtures {}",
+            first.display()
+        ))?;
+        let result = result.into_value(Span::test_data())?;
+        assert_eq!(result.as_record()?.get("files"), Some(&Value::test_int(1)));
+
+        let result = plugin_test.eval("cloud open memory://fixtures/dir/foo.txt")?;
+        assert_eq!(
+            result.into_value(Span::test_data())?,
+            Value::test_string("hello")
+        );
+        // Attributes survive the round trip
+        let _ = plugin_test.eval(&format!(
+            "cloud memory export --store fixtures {}",
+            second.display()
+        ))?;
+        let archive = std::fs::read(&second)?;
+        let record = b"NU_PLUGIN_CLOUD.metadata.owner=me";
+        assert!(archive.windows(record.len()).any(|w| w == record));
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_local_paths() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("polars", plugin.into())?;
+        let _ = plugin_test.add_decl(Box::new(Get))?;
+        let dir =
+            std::env::temp_dir().join(format!("nu_plugin_cloud_local_{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+
+        // Bare paths are saved and opened as file urls
+        let _ = plugin_test.eval(&format!(
+            "'hello' | cloud save {}",
+            dir.join("foo.txt").display()
+        ))?;
+        let result = plugin_test.eval(&format!(
+            "cloud open file://{}",
+            dir.join("foo.txt").display()
+        ))?;
+        assert_eq!(
+            result.into_value(Span::test_data())?,
+            Value::test_string("hello")
+        );
+        let result = plugin_test.eval(&format!("cloud ls {}/*.txt | get url", dir.display()))?;
+        assert_eq!(
+            result.into_value(Span::test_data())?,
+            Value::test_list(vec![Value::test_string(format!(
+                "file://{}",
+                dir.join("foo.txt").display()
+            ))])
+        );
+
+        assert!(has_scheme("s3://bucket/key"));
+        assert!(has_scheme("file:data.csv"));
+        assert!(!has_scheme("./data.csv"));
+        assert!(!has_scheme("C:\\data.csv"));
+
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_literal_key() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("polars", plugin.into())?;
+        let _ = plugin_test.add_decl(Box::new(Get))?;
+        let _ =
+            plugin_test.eval("'hello' | cloud save --literal-key 'memory:/why?/50%25#1.txt'")?;
+
+        // The listed url percent-encodes the key so that it can be opened again
+        let result = plugin_test.eval("cloud ls --recursive memory:/ | get url")?;
+        assert_eq!(
+            result.into_value(Span::test_data())?,
+            Value::test_list(vec![Value::test_string("memory:/why%3F/50%2525%231.txt")])
+        );
+        for url in [
+            "'memory:/why%3F/50%2525%231.txt'",
+            "--literal-key 'memory:/why?/50%25#1.txt'",
+        ] {
+            let result = plugin_test.eval(&format!("cloud open {url}"))?;
+            assert_eq!(
+                result.into_value(Span::test_data())?,
+                Value::test_string("hello")
+            );
+        }
+
+        // Dot segments are rejected rather than resolved to another key
+        let _ = plugin_test.eval("'other' | cloud save memory:/b.txt")?;
+        for url in [
+            "memory:/a/../b.txt",
+            "memory:/a/%2E%2E/b.txt",
+            "--literal-key memory:/a/../b.txt",
+        ] {
+            assert!(plugin_test.eval(&format!("cloud open {url}")).is_err());
+        }
+
+        // Only s3 keys that object_store can't represent are accessed through the sdk
+        let raw_key = |url: &str| {
+            raw_s3_key(&Spanned {
+                item: url.to_string(),
+                span: Span::test_data(),
+            })
+            .map(|raw_key| (raw_key.bucket_url.item, raw_key.key))
+        };
+        assert_eq!(
+            raw_key("s3://bucket/a/../b.txt"),
+            Some(("s3://bucket/".into(), "a/../b.txt".into()))
+        );
+        assert_eq!(
+            raw_key("s3://bucket//a//b"),
+            Some(("s3://bucket/".into(), "/a//b".into()))
+        );
+        assert_eq!(raw_key("s3://bucket/a/b.txt"), None);
+        assert_eq!(raw_key("memory:/a/../b.txt"), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_complete_url() -> Result<(), Box<dyn std::error::Error>> {
+        // The completer inserts a placeholder at the cursor, after `s3://bu`
+        let mut call = Call::new(Span::new(0, 8));
+        call.add_positional(Expression::new_unknown(
+            Expr::String("s3://bua".into()),
+            Span::new(9, 17),
+            Type::String,
+        ));
+        let call = DynamicCompletionCall {
+            call,
+            strip: true,
+            pos: 16,
+        };
+        assert_eq!(complete::typed_prefix(&call), Some("s3://bu".into()));
+
+        let values = |suggestions: Vec<DynamicSuggestion>| {
+            suggestions
+                .into_iter()
+                .map(|suggestion| suggestion.value)
+                .collect::<Vec<_>>()
+        };
+        let stores = ["default".to_string(), "scratch".to_string()];
+        assert_eq!(
+            values(complete::scheme_suggestions("mem", &stores)),
+            vec!["memory:/", "memory://scratch/"]
+        );
+        assert!(complete::scheme_suggestions("./data", &stores).is_empty());
+
+        let aliases = Aliases::from_value(&Value::test_record(record!(
+            "logs" => Value::test_string("s3://bucket/logs"),
+            "lake" => Value::test_string("memory://scratch/"),
+        )))?;
+        assert_eq!(
+            values(complete::alias_suggestions("lo", &aliases)),
+            vec!["logs:"]
+        );
+        assert_eq!(
+            values(complete::alias_suggestions("l", &aliases)),
+            vec!["lake:", "logs:"]
+        );
+        let (name, base, rest) = aliases.split("lake:a/c").expect("lake is an alias");
+        assert_eq!(
+            (name, base.as_str(), rest),
+            ("lake", "memory://scratch/", "a/c")
+        );
+
+        let plugin = CloudPlugin::default();
+        let store = InMemory::new();
+        let suggestions = plugin.rt.block_on(async {
+            for key in ["a/b.txt", "a/c/d.txt", "e.txt"] {
+                store.put(&Path::from(key), "data".into()).await?;
+            }
+            let root = Url::parse("memory://scratch/")?;
+            complete::list_suggestions(&store, &root, &Path::from("a"))
+                .await
+                .map_err(Box::<dyn std::error::Error>::from)
+        })?;
+        assert_eq!(
+            values(suggestions.clone()),
+            vec!["memory://scratch/a/c/", "memory://scratch/a/b.txt"]
+        );
+        assert_eq!(
+            values(complete::from_alias(suggestions, name, &base)),
+            vec!["lake:a/c/", "lake:a/b.txt"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_aliases() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("polars", plugin.into())?;
+        let _ = plugin_test.add_decl(Box::new(Get))?;
+        let result = plugin_test.eval(
+            "$env.config.plugins.polars = { aliases: { logs: 'memory:/logs/', readme: 'memory:/README.md' } }; 'hello' | cloud save logs:2024/a.txt; 'docs' | cloud save readme:; [(cloud open memory:/logs/2024/a.txt) (cloud open readme:) (cloud ls logs: | get 0.name)]",
+        )?;
+        assert_eq!(
+            result.into_value(Span::test_data())?,
+            Value::test_list(vec![
+                Value::test_string("hello"),
+                Value::test_string("docs"),
+                Value::test_string("logs/2024"),
+            ])
+        );
+
+        let Err(error) = plugin_test.eval(
+            "$env.config.plugins.polars = { aliases: { s3: 'memory:/' } }; cloud ls memory:/",
+        ) else {
+            panic!("s3 should be rejected as an alias name");
+        };
+        assert!(format!("{error:?}").contains("Alias s3"), "{error:?}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_directories() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("polars", plugin.into())?;
+        let _ = plugin_test.add_decl(Box::new(ToCsv))?;
+        let _ = plugin_test.add_decl(Box::new(Select))?;
+        let result = plugin_test.eval_with(
+            "[[a b]; [1 2]] | cloud save memory:/dir/foo.csv; [[a b]; [1 2]] | cloud save memory:/bar.csv; cloud ls memory:/ | select name type",
+            PipelineData::Empty,
+        )?;
+        let value = result.into_value(Span::test_data())?;
+        assert_eq!(
+            value,
+            Value::test_list(vec![
+                Value::test_record(record!(
+                    "name" => Value::test_string("dir"),
+                    "type" => Value::test_string("dir"),
+                )),
+                Value::test_record(record!(
+                    "name" => Value::test_string("bar.csv"),
+                    "type" => Value::test_string("file"),
+                )),
+            ])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_recursive() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("polars", plugin.into())?;
+        let _ = plugin_test.add_decl(Box::new(ToCsv))?;
+        let _ = plugin_test.add_decl(Box::new(Select))?;
+        let result = plugin_test.eval_with(
+            "[[a b]; [1 2]] | cloud save memory:/dir/foo.csv; [[a b]; [1 2]] | cloud save memory:/bar.csv; cloud ls --recursive memory:/ | select name type",
+            PipelineData::Empty,
+        )?;
+        let value = result.into_value(Span::test_data())?;
+        assert_eq!(
+            value,
+            Value::test_list(vec![
+                Value::test_record(record!(
+                    "name" => Value::test_string("bar.csv"),
+                    "type" => Value::test_string("file"),
+                )),
+                Value::test_record(record!(
+                    "name" => Value::test_string("dir/foo.csv"),
+                    "type" => Value::test_string("file"),
+                )),
+            ])
+        );
+
+        let Err(error) = plugin_test.eval("cloud ls --recursive --max-depth 2 memory:/") else {
+            panic!("--recursive should be rejected with --max-depth");
+        };
+        assert_eq!(
+            LabeledError::from_diagnostic(&error).code.as_deref(),
+            Some("nu::shell::incompatible_parameters")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_start_after() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("polars", plugin.into())?;
+        let _ = plugin_test.add_decl(Box::new(ToCsv))?;
+        let _ = plugin_test.add_decl(Box::new(Select))?;
+        let result = plugin_test.eval_with(
+            "[[a b]; [1 2]] | cloud save memory:/a.csv; [[a b]; [1 2]] | cloud save memory:/b.csv; [[a b]; [1 2]] | cloud save memory:/c.csv; cloud ls --recursive --start-after a.csv memory:/ | select name",
+            PipelineData::Empty,
+        )?;
+        let value = result.into_value(Span::test_data())?;
+        assert_eq!(
+            value,
+            Value::test_list(vec![
+                Value::test_record(record!("name" => Value::test_string("b.csv"))),
+                Value::test_record(record!("name" => Value::test_string("c.csv"))),
+            ])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_url() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("polars", plugin.into())?;
+        let _ = plugin_test.add_decl(Box::new(ToCsv))?;
+        let _ = plugin_test.add_decl(Box::new(Get))?;
+        let result = plugin_test.eval_with(
+            "[[a b]; [1 2]] | cloud save memory:/dir/foo.csv; cloud ls memory:/dir | get url",
+            PipelineData::Empty,
+        )?;
+        let value = result.into_value(Span::test_data())?;
+        assert_eq!(
+            value,
+            Value::test_list(vec![Value::test_string("memory:/dir/foo.csv")])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_from_list() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("polars", plugin.into())?;
+        let _ = plugin_test.add_decl(Box::new(ToCsv))?;
+        let _ = plugin_test.add_decl(Box::new(FromCsv))?;
+        let _ = plugin_test.add_decl(Box::new(Each))?;
+        let result = plugin_test.eval_with(
+            "[[a b]; [1 2]] | cloud save memory:/dir/foo.csv; cloud ls memory:/dir | each { cloud open }",
+            PipelineData::Empty,
+        )?;
+        let value = result.into_value(Span::test_data())?;
+        assert_eq!(
+            value,
+            Value::test_list(vec![Value::test_list(vec![Value::test_record(record!(
+                "a" => Value::test_int(1),
+                "b" => Value::test_int(2),
+            ))])])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_rm_from_list() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("polars", plugin.into())?;
+        let _ = plugin_test.add_decl(Box::new(ToCsv))?;
+        let _ = plugin_test.add_decl(Box::new(Length))?;
+        let result = plugin_test.eval_with(
+            "[[a b]; [1 2]] | cloud save memory:/foo.csv; [[a b]; [1 2]] | cloud save memory:/bar.csv; cloud ls memory:/ | cloud rm; cloud ls memory:/ | length",
+            PipelineData::Empty,
+        )?;
+        let value = result.into_value(Span::test_data())?;
+        assert_eq!(value, Value::test_int(0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_list_glob() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("polars", plugin.into())?;
+        let _ = plugin_test.add_decl(Box::new(ToCsv))?;
+        let _ = plugin_test.add_decl(Box::new(Get))?;
+        let result = plugin_test.eval_with(
+            "[[a b]; [1 2]] | cloud save memory:/logs/2024-01/a.csv; [[a b]; [1 2]] | cloud save memory:/logs/2024-02/deep/b.csv; 'hello' | cloud save memory:/logs/2024-02/c.txt; cloud ls memory:/logs/2024-*/**/*.csv | get name",
+            PipelineData::Empty,
+        )?;
+        let value = result.into_value(Span::test_data())?;
+        assert_eq!(
+            value,
+            Value::test_list(vec![
+                Value::test_string("logs/2024-01/a.csv"),
+                Value::test_string("logs/2024-02/deep/b.csv"),
+            ])
+        );
+
+        let result = plugin_test.eval(
+            "cloud ls --start-after logs/2024-01/a.csv memory:/logs/2024-*/**/*.csv | get name",
+        )?;
+        assert_eq!(
+            result.into_value(Span::test_data())?,
+            Value::test_list(vec![Value::test_string("logs/2024-02/deep/b.csv")])
+        );
+        for flag in ["--recursive", "--max-depth 2"] {
+            let Err(error) = plugin_test.eval(&format!("cloud ls {flag} memory:/logs/*/*.csv"))
+            else {
+                panic!("{flag} should be rejected with a glob");
+            };
+            assert_eq!(
+                LabeledError::from_diagnostic(&error).code.as_deref(),
+                Some("nu::shell::incompatible_parameters")
+            );
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_glob() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("polars", plugin.into())?;
+        let _ = plugin_test.add_decl(Box::new(ToCsv))?;
+        let _ = plugin_test.add_decl(Box::new(FromCsv))?;
+        let result = plugin_test.eval_with(
+            "[[a b]; [1 2]] | cloud save memory:/foo.csv; [[a b]; [3 4]] | cloud save memory:/bar.csv; cloud open memory:/*.csv",
+            PipelineData::Empty,
+        )?;
+        let value = result.into_value(Span::test_data())?;
+        assert_eq!(
+            value,
+            Value::test_list(vec![
+                Value::test_record(record!(
+                    "source" => Value::test_string("memory:/bar.csv"),
+                    "a" => Value::test_int(3),
+                    "b" => Value::test_int(4),
+                )),
+                Value::test_record(record!(
+                    "source" => Value::test_string("memory:/foo.csv"),
+                    "a" => Value::test_int(1),
+                    "b" => Value::test_int(2),
+                )),
+            ])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_rm_glob() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("polars", plugin.into())?;
+        let _ = plugin_test.add_decl(Box::new(ToCsv))?;
+        let _ = plugin_test.add_decl(Box::new(Get))?;
+        let result = plugin_test.eval_with(
+            "[[a b]; [1 2]] | cloud save memory:/foo.csv; 'hello' | cloud save memory:/bar.txt; cloud rm memory:/*.csv; cloud ls memory:/ | get name",
+            PipelineData::Empty,
+        )?;
+        let value = result.into_value(Span::test_data())?;
+        assert_eq!(value, Value::test_list(vec![Value::test_string("bar.txt")]));
+        Ok(())
+    }
+
+    #[test]
+    fn test_rm_recursive() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("polars", plugin.into())?;
+        let _ = plugin_test.add_decl(Box::new(ToCsv))?;
+        let _ = plugin_test.add_decl(Box::new(Get))?;
+        let result = plugin_test.eval_with(
+            "[[a b]; [1 2]] | cloud save memory:/dir/foo.csv; [[a b]; [1 2]] | cloud save memory:/dir/sub/bar.csv; [[a b]; [1 2]] | cloud save memory:/baz.csv; cloud rm --recursive memory:/dir | get url",
+            PipelineData::Empty,
+        )?;
+        let value = result.into_value(Span::test_data())?;
+        assert_eq!(
+            value,
+            Value::test_list(vec![
+                Value::test_string("memory:/dir/foo.csv"),
+                Value::test_string("memory:/dir/sub/bar.csv"),
+            ])
+        );
+
+        // A single file is deleted if nothing is below it, and reported if it doesn't exist
+        let result = plugin_test.eval("cloud rm --recursive memory:/baz.csv | get url")?;
+        assert_eq!(
+            result.into_value(Span::test_data())?,
+            Value::test_list(vec![Value::test_string("memory:/baz.csv")])
+        );
+        let result = plugin_test.eval("cloud rm --recursive memory:/dir")?;
+        let Err(error) = result.into_value(Span::test_data()) else {
+            panic!("removing a missing prefix should fail");
+        };
+        assert_eq!(
+            LabeledError::from_diagnostic(&error).code.as_deref(),
+            Some("nu_plugin_cloud::not_found")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_rm_dry_run() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("polars", plugin.into())?;
+        let _ = plugin_test.add_decl(Box::new(ToCsv))?;
+        let _ = plugin_test.add_decl(Box::new(Length))?;
+        let result = plugin_test.eval_with(
+            "[[a b]; [1 2]] | cloud save memory:/dir/foo.csv; cloud rm --recursive --dry-run memory:/dir; cloud ls --recursive memory:/ | length",
+            PipelineData::Empty,
+        )?;
+        let value = result.into_value(Span::test_data())?;
+        assert_eq!(value, Value::test_int(1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_multiple() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("polars", plugin.into())?;
+        let _ = plugin_test.add_decl(Box::new(ToCsv))?;
+        let _ = plugin_test.add_decl(Box::new(FromCsv))?;
+        let result = plugin_test.eval_with(
+            "[[a b]; [1 2]] | cloud save memory:/foo.csv; 'hello' | cloud save memory:/bar.txt; cloud open memory:/foo.csv memory:/bar.txt",
+            PipelineData::Empty,
+        )?;
+        let value = result.into_value(Span::test_data())?;
+        assert_eq!(
+            value,
+            Value::test_list(vec![
+                Value::test_list(vec![Value::test_record(record!(
+                    "a" => Value::test_int(1),
+                    "b" => Value::test_int(2),
+                ))]),
+                Value::test_string("hello"),
+            ])
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_open_range() -> Result<(), Box<dyn std::error::Error>> {
+        let plugin = CloudPlugin::default();
+        let mut plugin_test = PluginTest::new("polars", plugin.into())?;
+        let result = plugin_test.eval_with(
+            "'hello world' | cloud save memory:/foo.txt; [(cloud open --range 0..4 memory:/foo.txt) (cloud open --tail 5b memory:/foo.txt)]",
+            PipelineData::Empty,
+        )?;
+        let value = result.into_value(Span::test_data())?;
+        assert_eq!(
+            value,
+            Value::test_list(vec![
+                Value::test_string("hello"),
+                Value::test_string("world"),
+            ])
+        );
+        Ok(())
+    }
+
+    /// Reads the head of an http request from stream, returning its first line
+    fn read_request(stream: &mut BufReader<TcpStream>) -> std::io::Result<String> {
+        let mut first = String::new();
+        stream.read_line(&mut first)?;
+        let mut line = String::new();
+        while stream.read_line(&mut line)? > 2 {
+            line.clear();
+        }
+        Ok(first.trim_end().to_string())
+    }
+
+    /// Accepts a single connection of listener, acting as a proxy if tunnel is set, and
+    /// answers one request. Returns the first lines of the requests it received.
+    fn serve_once(listener: TcpListener, tunnel: bool) -> JoinHandle<std::io::Result<Vec<String>>> {
+        thread::spawn(move || {
+            let (stream, _) = listener.accept()?;
+            let mut stream = BufReader::new(stream);
+            let mut requests = vec![read_request(&mut stream)?];
+            if tunnel {
+                stream
+                    .get_mut()
+                    .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n")?;
+                requests.push(read_request(&mut stream)?);
+            }
+            stream.get_mut().write_all(
+                b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\nok",
+            )?;
+            Ok(requests)
+        })
+    }
+
+    /// Sends a GET request to uri with the sdk http client of settings
+    fn get_status(settings: &ClientSettings, uri: &str) -> Result<u16, Box<dyn std::error::Error>> {
+        let client = S3HttpClient::new(settings, Span::test_data())?;
+        let request = HttpRequest::try_from(http::Request::get(uri).body(SdkBody::empty())?)?;
+        let runtime = tokio::runtime::Runtime::new()?;
+        let response = runtime.block_on(client.call(request))?;
+        Ok(response.status().as_u16())
+    }
+
+    #[test]
+    fn test_s3_http_client_proxy() -> Result<(), Box<dyn std::error::Error>> {
+        let proxy = TcpListener::bind("127.0.0.1:0")?;
+        let settings = ClientSettings {
+            proxy: Some(format!("http://{}", proxy.local_addr()?)),
+            ..Default::default()
+        };
+        let server = serve_once(proxy, true);
+
+        // .invalid never resolves, so the request only succeeds through the proxy
+        let status = get_status(&settings, "http://bucket.invalid:9000/a/../b.txt")?;
+        assert_eq!(status, 200);
+        let requests = server.join().expect("proxy panicked")?;
+        assert_eq!(
+            requests,
+            vec![
+                "CONNECT bucket.invalid:9000 HTTP/1.1".to_string(),
+                "GET /a/../b.txt HTTP/1.1".to_string(),
+            ]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_s3_http_client_no_proxy() -> Result<(), Box<dyn std::error::Error>> {
+        // Nothing listens on the port of the proxy once the listener is dropped
+        let proxy = TcpListener::bind("127.0.0.1:0")?.local_addr()?;
+        let server = TcpListener::bind("127.0.0.1:0")?;
+        let uri = format!("http://{}/bucket/key", server.local_addr()?);
+        let mut settings = ClientSettings {
+            proxy: Some(format!("http://{proxy}")),
+            ..Default::default()
+        };
+
+        assert!(get_status(&settings, &uri).is_err());
+
+        settings.no_proxy = Some("127.0.0.1".into());
+        let server = serve_once(server, false);
+        assert_eq!(get_status(&settings, &uri)?, 200);
+        let requests = server.join().expect("server panicked")?;
+        assert_eq!(requests, vec!["GET /bucket/key HTTP/1.1".to_string()]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_s3_http_client_ca_bundle() -> Result<(), Box<dyn std::error::Error>> {
+        let dir = std::env::temp_dir().join(format!("nu_plugin_cloud_ca_{}", std::process::id()));
+        std::fs::create_dir_all(&dir)?;
+        let missing = dir.join("missing.pem");
+        let invalid = dir.join("invalid.pem");
+        std::fs::write(
+            &invalid,
+            "-----BEGIN CERTIFICATE-----\nbm90IGEgY2VydGlmaWNhdGU=\n-----END CERTIFICATE-----\n",
+        )?;
+
+        for path in [missing, invalid] {
+            let settings = ClientSettings {
+                ca_bundle: Some(path.clone()),
+                ..Default::default()
+            };
+            let Err(error) = S3HttpClient::new(&settings, Span::test_data()) else {
+                panic!("{} should be rejected", path.display());
+            };
+            assert!(
+                error.to_string().contains(&path.display().to_string()),
+                "{error}"
+            );
+        }
+        std::fs::remove_dir_all(&dir)?;
+        Ok(())
+    }
 }
@@ -0,0 +1,103 @@
+use object_store::{MultipartUpload, PutPayloadMut, PutResult};
+use tokio::task::JoinSet;
+
+/// The smallest part size accepted by S3, also used as the default part size
+pub(crate) const MIN_PART_SIZE: usize = 5 * 1024 * 1024;
+/// The default number of parts uploaded at the same time
+pub(crate) const DEFAULT_CONCURRENCY: usize = 8;
+/// S3 accepts at most 10,000 parts per multipart upload
+const MAX_PARTS: u64 = 10_000;
+/// When the size of the input isn't known upfront, the part size is doubled every
+/// time this many parts have been uploaded. Starting at 5MiB this allows for
+/// uploads of roughly 5TiB, the maximum object size of S3, within 10,000 parts.
+const PARTS_PER_SIZE_STEP: u64 = 1_000;
+
+/// Uploads data in parts, with at most `concurrency` parts in flight at the same time.
+///
+/// Unlike [`object_store::WriteMultipart`], writes wait for a part to finish uploading
+/// when too many are in flight, so that memory use is bounded by `concurrency` parts, and
+/// the part size grows for large inputs so that the upload stays within S3's part limit.
+pub(crate) struct PartWriter {
+    upload: Box<dyn MultipartUpload>,
+    buffer: PutPayloadMut,
+    part_size: usize,
+    concurrency: usize,
+    parts: u64,
+    tasks: JoinSet<object_store::Result<()>>,
+}
+
+impl PartWriter {
+    /// Creates a writer using parts of at least `part_size` bytes. When the total size is
+    /// known, the part size is increased as needed to upload it in at most 10,000 parts.
+    pub fn new(
+        upload: Box<dyn MultipartUpload>,
+        part_size: usize,
+        concurrency: usize,
+        size_hint: Option<u64>,
+    ) -> Self {
+        let min_part_size = size_hint.map_or(0, |size| size.div_ceil(MAX_PARTS) as usize);
+        Self {
+            upload,
+            buffer: PutPayloadMut::new(),
+            part_size: part_size.max(min_part_size),
+            concurrency: concurrency.max(1),
+            parts: 0,
+            tasks: JoinSet::new(),
+        }
+    }
+
+    pub async fn write(&mut self, mut buf: &[u8]) -> object_store::Result<()> {
+        while !buf.is_empty() {
+            let remaining = self.part_size - self.buffer.content_length();
+            let to_read = buf.len().min(remaining);
+            self.buffer.extend_from_slice(&buf[..to_read]);
+            if to_read == remaining {
+                self.put_part().await?;
+            }
+            buf = &buf[to_read..];
+        }
+        Ok(())
+    }
+
+    /// Uploads the remaining buffered data and completes the upload. On failure the upload
+    /// is left incomplete, and should be aborted with [`Self::abort`].
+    pub async fn finish(&mut self) -> object_store::Result<PutResult> {
+        if !self.buffer.is_empty() {
+            self.put_part().await?;
+        }
+        self.wait_for_capacity(0).await?;
+        self.upload.complete().await
+    }
+
+    /// Aborts the upload, cleaning up any parts that were already uploaded
+    pub async fn abort(&mut self) -> object_store::Result<()> {
+        self.tasks.shutdown().await;
+        self.upload.abort().await
+    }
+
+    async fn put_part(&mut self) -> object_store::Result<()> {
+        self.wait_for_capacity(self.concurrency - 1).await?;
+        let part = std::mem::take(&mut self.buffer);
+        self.tasks.spawn(self.upload.put_part(part.into()));
+
+        self.parts += 1;
+        if self.parts.is_multiple_of(PARTS_PER_SIZE_STEP) {
+            self.part_size *= 2;
+        }
+        Ok(())
+    }
+
+    /// Waits until at most `max_in_flight` parts are still being uploaded
+    async fn wait_for_capacity(&mut self, max_in_flight: usize) -> object_store::Result<()> {
+        while self.tasks.len() > max_in_flight {
+            match self.tasks.join_next().await {
+                Some(Ok(result)) => result?,
+                Some(Err(e)) => {
+                    return Err(object_store::Error::JoinError { source: e });
+                }
+                None => break,
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,16 +1,32 @@
-use std::{path::PathBuf, str::FromStr, vec};
+use std::{ops::Bound, path::Path, vec};
 
-use bytes::Buf;
+use bytes::{Buf, Bytes};
+use futures::{StreamExt, TryStreamExt, stream};
 use log::debug;
-use nu_plugin::{EngineInterface, PluginCommand};
+use nu_plugin::{DynamicCompletionCall, EngineInterface, PluginCommand};
 use nu_protocol::{
-    ByteStream, ByteStreamType, Category, DataSource, Example, IntoInterruptiblePipelineData,
-    LabeledError, PipelineData, PipelineMetadata, ShellError, Signature, Spanned, SyntaxShape,
-    Type,
+    ByteStream, ByteStreamType, Category, DataSource, DynamicSuggestion, Example, Filesize,
+    IntoInterruptiblePipelineData, LabeledError, NuGlob, PipelineData, PipelineMetadata, Range,
+    Record, ShellError, Signature, Span, Spanned, SyntaxShape, Type, Value, engine::ArgType,
 };
-use url::Url;
 
-use crate::CloudPlugin;
+use object_store::{GetOptions, GetRange};
+
+use crate::{
+    CloudPlugin,
+    cache::{READ_ERROR, read_body},
+    error::CloudError,
+    progress::Progress,
+    providers::{ClientSettings, versions_unsupported},
+};
+
+use super::{
+    RawKey, client_flags, client_settings, complete::complete_url, glob::expand_url, input_urls,
+    literal_key_flag, literal_url, parse_url_arg, progress_enabled, raw_s3_key, resolve_glob_alias,
+};
+
+/// The maximum number of objects fetched at the same time when opening multiple urls
+const MAX_CONCURRENT_FETCHES: usize = 8;
 
 pub struct Open;
 
@@ -22,10 +38,43 @@ impl PluginCommand for Open {
     }
 
     fn signature(&self) -> nu_protocol::Signature {
-        Signature::build("cloud open")
-            .input_output_types(vec![(Type::Nothing, Type::Any), (Type::String, Type::Any)])
-            .rest("url", SyntaxShape::String, "The cloud url to file to open.")
+        client_flags(literal_key_flag(Signature::build("cloud open")))
+            .input_output_types(vec![
+                (Type::Nothing, Type::Any),
+                (Type::String, Type::Any),
+                (Type::record(), Type::Any),
+                (Type::table(), Type::Any),
+            ])
+            .rest(
+                "url",
+                SyntaxShape::GlobPattern,
+                "The cloud url or glob pattern of the files to open.",
+            )
             .switch("raw", "open file as raw binary", Some('r'))
+            .named(
+                "range",
+                SyntaxShape::Range,
+                "only read the given range of bytes, i.e. 0..4095. Implies --raw",
+                None,
+            )
+            .named(
+                "tail",
+                SyntaxShape::Filesize,
+                "only read the given number of bytes from the end of the file. Implies --raw",
+                None,
+            )
+            .switch(
+                "progress",
+                "report download progress on stderr, the default when stderr is a terminal",
+                Some('p'),
+            )
+            .switch("quiet", "don't report download progress", Some('q'))
+            .named(
+                "version",
+                SyntaxShape::String,
+                "open the given version of the file in a versioned bucket",
+                None,
+            )
             .category(Category::FileSystem)
     }
 
@@ -34,11 +83,43 @@ impl PluginCommand for Open {
     }
 
     fn examples(&self) -> Vec<Example<'_>> {
-        vec![Example {
-            description: "Load a file from s3.",
-            example: "cloud open s3://mybucket/file.txt",
-            result: None,
-        }]
+        vec![
+            Example {
+                description: "Load a file from s3.",
+                example: "cloud open s3://mybucket/file.txt",
+                result: None,
+            },
+            Example {
+                description: "Load several files from s3 into a single table with a source column.",
+                example: "cloud open s3://mybucket/2024.csv s3://mybucket/2025.csv",
+                result: None,
+            },
+            Example {
+                description: "Load every csv file below a prefix into a single table with a source column.",
+                example: "cloud open s3://mybucket/data/**/*.csv",
+                result: None,
+            },
+            Example {
+                description: "Read the first 4KiB of a file from s3 without downloading all of it.",
+                example: "cloud open --range 0..<4096 s3://mybucket/data.parquet",
+                result: None,
+            },
+            Example {
+                description: "Read the last megabyte of a log file from s3.",
+                example: "cloud open --tail 1MB s3://mybucket/app.log",
+                result: None,
+            },
+            Example {
+                description: "Load an older version of a file from a versioned s3 bucket.",
+                example: "cloud open --version 3HL4kqtJlcpXroDTDmJ.rmSpXd3dIbrHY s3://mybucket/file.txt",
+                result: None,
+            },
+            Example {
+                description: "Load the files listed by cloud ls.",
+                example: "cloud ls s3://mybucket/data | where name ends-with .csv | cloud open",
+                result: None,
+            },
+        ]
     }
 
     fn run(
@@ -51,7 +132,22 @@ impl PluginCommand for Open {
         plugin
             .rt
             .block_on(command(plugin, engine, call, input))
-            .map_err(LabeledError::from)
+            .map_err(|e| LabeledError::from(*e))
+    }
+
+    #[expect(deprecated, reason = "completion is still experimental in nushell")]
+    fn get_dynamic_completion(
+        &self,
+        plugin: &Self::Plugin,
+        engine: &EngineInterface,
+        call: DynamicCompletionCall,
+        arg_type: ArgType,
+        _experimental: nu_protocol::engine::ExperimentalMarker,
+    ) -> Option<Vec<DynamicSuggestion>> {
+        match arg_type {
+            ArgType::Positional(_) => complete_url(plugin, engine, &call),
+            ArgType::Flag(_) => None,
+        }
     }
 }
 
@@ -59,26 +155,369 @@ async fn command(
     plugin: &CloudPlugin,
     engine: &EngineInterface,
     call: &nu_plugin::EvaluatedCall,
-    _input: PipelineData,
-) -> Result<PipelineData, ShellError> {
+    input: PipelineData,
+) -> Result<PipelineData, Box<ShellError>> {
     let call_span = call.head;
     let raw = call.has_flag("raw")?;
-    let spanned_path: Spanned<PathBuf> = call.req(0)?;
-    let path = spanned_path.item;
-    let url = path
-        .to_str()
-        .expect("The path should already be unicode")
-        .to_string();
-    let url = Spanned {
-        item: Url::from_str(&url).map_err(|e| ShellError::IncorrectValue {
-            msg: format!("Invalid Url: {e}"),
-            val_span: spanned_path.span,
+    let version: Option<String> = call.get_flag("version")?;
+    let progress_enabled = progress_enabled(call)?;
+    let settings = client_settings(engine, call)?;
+    let literal_key = call.has_flag("literal-key")?;
+
+    let args: Vec<Spanned<NuGlob>> = call.rest(0)?;
+    let mut urls = if args.is_empty() {
+        input_urls(engine, input, call_span)?
+    } else {
+        let mut urls = vec![];
+        for arg in args {
+            let arg = resolve_glob_alias(engine, arg)?;
+            if literal_key {
+                let arg = Spanned {
+                    item: arg.item.as_ref().to_string(),
+                    span: arg.span,
+                };
+                match raw_s3_key(&arg) {
+                    Some(_) => urls.push(arg),
+                    None => urls.push(literal_url(&arg, call_span)?),
+                }
+            } else {
+                urls.extend(expand_url(plugin, engine, arg, &settings, call_span).await?);
+            }
+        }
+        urls
+    };
+    if urls.is_empty() {
+        return Err(Box::new(ShellError::MissingParameter {
+            param_name: "url".into(),
+            span: call_span,
+        }));
+    }
+
+    // Keys that object_store can't represent are read with the S3 sdk, see [`RawKey`]
+    let raw_keys: Vec<Option<RawKey>> = urls
+        .iter()
+        .map(|url| literal_key.then(|| raw_s3_key(url)).flatten())
+        .collect();
+
+    if let Some(range) = byte_range(call)? {
+        let opts = GetOptions {
+            range: Some(range),
+            version,
+            ..GetOptions::default()
+        };
+        return open_ranges(
+            plugin,
+            engine,
+            urls.into_iter().zip(raw_keys).collect(),
+            opts,
+            &settings,
+            progress_enabled,
             call_span,
-        })?,
-        span: spanned_path.span,
+        )
+        .await;
+    }
+
+    let label = match urls.as_slice() {
+        [url] => format!("Downloading {}", url.item),
+        urls => format!("Downloading {} files", urls.len()),
     };
+    let progress = Progress::new(label, progress_enabled);
+
+    // Objects are fetched concurrently, converting them requires calls back into the engine
+    // and happens afterwards, one at a time.
+    let mut fetched: Vec<Bytes> = stream::iter(urls.iter().zip(&raw_keys))
+        .map(|(url, raw_key)| async {
+            match raw_key {
+                Some(raw_key) => {
+                    let opts = GetOptions {
+                        version: version.clone(),
+                        ..GetOptions::default()
+                    };
+                    fetch_raw(plugin, engine, raw_key, &opts, &settings, call_span).await
+                }
+                None => {
+                    fetch(
+                        plugin,
+                        engine,
+                        url,
+                        version.as_deref(),
+                        &settings,
+                        &progress,
+                        call_span,
+                    )
+                    .await
+                }
+            }
+        })
+        .buffered(MAX_CONCURRENT_FETCHES)
+        .try_collect()
+        .await?;
+    progress.finish();
+
+    if urls.len() == 1 {
+        return convert(engine, call, &urls.remove(0), fetched.remove(0), raw);
+    }
+
+    let mut values = vec![];
+    for (url, bytes) in urls.into_iter().zip(fetched) {
+        let value = convert(engine, call, &url, bytes, raw)?.into_value(call_span)?;
+        values.push((url, value));
+    }
+    Ok(PipelineData::Value(combine(values, call_span), None))
+}
 
-    let bytes = plugin.cache.get(engine, &url, call_span).await?;
+/// Parses the --range and --tail flags into the range of bytes to read
+fn byte_range(call: &nu_plugin::EvaluatedCall) -> Result<Option<GetRange>, Box<ShellError>> {
+    let call_span = call.head;
+    let range: Option<Spanned<Range>> = call.get_flag("range")?;
+    let tail: Option<Spanned<Filesize>> = call.get_flag("tail")?;
+
+    match (range, tail) {
+        (Some(range), Some(tail)) => Err(Box::new(ShellError::IncompatibleParameters {
+            left_message: "can't use --range".into(),
+            left_span: range.span,
+            right_message: "together with --tail".into(),
+            right_span: tail.span,
+        })),
+        (None, Some(tail)) if tail.item.get() > 0 => {
+            Ok(Some(GetRange::Suffix(tail.item.get() as u64)))
+        }
+        (None, Some(tail)) => Err(Box::new(ShellError::IncorrectValue {
+            msg: "tail must be larger than zero".into(),
+            val_span: tail.span,
+            call_span,
+        })),
+        (Some(range), None) => {
+            let invalid = |msg: &str| {
+                Box::new(ShellError::IncorrectValue {
+                    msg: msg.into(),
+                    val_span: range.span,
+                    call_span,
+                })
+            };
+            let Range::IntRange(int_range) = range.item else {
+                return Err(invalid("range must be a range of integers"));
+            };
+            if int_range.start() < 0 || int_range.step() != 1 {
+                return Err(invalid("range must be ascending and start at or after 0"));
+            }
+            let start = int_range.start() as u64;
+            match int_range.end() {
+                Bound::Unbounded => Ok(Some(GetRange::Offset(start))),
+                Bound::Included(end) if end >= int_range.start() => {
+                    Ok(Some(GetRange::Bounded(start..end as u64 + 1)))
+                }
+                Bound::Excluded(end) if end > int_range.start() => {
+                    Ok(Some(GetRange::Bounded(start..end as u64)))
+                }
+                _ => Err(invalid("range must not be empty")),
+            }
+        }
+        (None, None) => Ok(None),
+    }
+}
+
+/// Reads a range of bytes from each url, as given by opts. Range reads bypass the cache
+/// and are returned as a raw stream.
+async fn open_ranges(
+    plugin: &CloudPlugin,
+    engine: &EngineInterface,
+    urls: Vec<(Spanned<String>, Option<RawKey>)>,
+    opts: GetOptions,
+    settings: &ClientSettings,
+    progress: bool,
+    call_span: Span,
+) -> Result<PipelineData, Box<ShellError>> {
+    let single = urls.len() == 1;
+    let mut values = vec![];
+    for (url, raw_key) in urls {
+        let data = match raw_key {
+            Some(raw_key) => {
+                let bytes = fetch_raw(plugin, engine, &raw_key, &opts, settings, call_span).await?;
+                PipelineData::ByteStream(
+                    ByteStream::read(
+                        bytes.reader(),
+                        call_span,
+                        engine.signals().clone(),
+                        ByteStreamType::Unknown,
+                    ),
+                    Some(range_metadata(&url.item)),
+                )
+            }
+            None => {
+                open_range(
+                    plugin,
+                    engine,
+                    &url,
+                    opts.clone(),
+                    settings,
+                    progress,
+                    call_span,
+                )
+                .await?
+            }
+        };
+        if single {
+            return Ok(data);
+        }
+        values.push(data.into_value(call_span)?);
+    }
+    Ok(PipelineData::Value(Value::list(values, call_span), None))
+}
+
+async fn open_range(
+    plugin: &CloudPlugin,
+    engine: &EngineInterface,
+    spanned_url: &Spanned<String>,
+    opts: GetOptions,
+    settings: &ClientSettings,
+    progress: bool,
+    call_span: Span,
+) -> Result<PipelineData, Box<ShellError>> {
+    let url = parse_url_arg(engine, spanned_url, call_span)?;
+    let (object_store, path) = plugin.parse_url(engine, &url, settings, call_span).await?;
+    if opts.version.is_some() && !object_store.supports_versions() {
+        return Err(Box::new(versions_unsupported(url.span)));
+    }
+    let result = object_store
+        .object_store()
+        .get_opts(&path, opts)
+        .await
+        .map_err(|e| CloudError::new(READ_ERROR, e, object_store.provider(), url.span))?;
+
+    let progress = Progress::new(format!("Downloading {}", spanned_url.item), progress);
+    progress.add_total(result.range.end - result.range.start);
+
+    let handle = plugin.rt.handle().clone();
+    let url_span = url.span;
+    let provider = object_store.provider();
+    let mut chunks = result.into_stream();
+    let chunks = std::iter::from_fn(move || {
+        let Some(chunk) = handle.block_on(chunks.next()) else {
+            progress.finish();
+            return None;
+        };
+        if let Ok(chunk) = &chunk {
+            progress.advance(chunk.len() as u64);
+        }
+        Some(chunk.map_err(|e| CloudError::new(READ_ERROR, e, provider, url_span).into()))
+    });
+
+    Ok(PipelineData::ByteStream(
+        ByteStream::from_result_iter(
+            chunks,
+            call_span,
+            engine.signals().clone(),
+            ByteStreamType::Unknown,
+        ),
+        Some(range_metadata(&spanned_url.item)),
+    ))
+}
+
+/// The metadata of a range read from url, which is returned unconverted
+fn range_metadata(url: &str) -> PipelineMetadata {
+    let content_type = Path::new(url)
+        .extension()
+        .and_then(|ext| detect_content_type(&ext.to_string_lossy()));
+    PipelineMetadata::default()
+        .with_data_source(DataSource::FilePath(Path::new(url).to_path_buf()))
+        .with_content_type(content_type)
+}
+
+/// Concatenates the opened values into a single table with a `source` column when
+/// every value is a table, otherwise returns a list of the opened values.
+fn combine(values: Vec<(Spanned<String>, Value)>, span: Span) -> Value {
+    let all_tables = values.iter().all(|(_, value)| match value {
+        Value::List { vals, .. } => vals.iter().all(|v| matches!(v, Value::Record { .. })),
+        _ => false,
+    });
+
+    if !all_tables {
+        return Value::list(values.into_iter().map(|(_, value)| value).collect(), span);
+    }
+
+    let rows = values
+        .into_iter()
+        .flat_map(|(url, value)| {
+            let source = Value::string(url.item, url.span);
+            value
+                .into_list()
+                .unwrap_or_default()
+                .into_iter()
+                .map(move |row| {
+                    let row_span = row.span();
+                    let record: Record = std::iter::once(("source".to_string(), source.clone()))
+                        .chain(row.into_record().unwrap_or_default())
+                        .collect();
+                    Value::record(record, row_span)
+                })
+        })
+        .collect();
+    Value::list(rows, span)
+}
+
+async fn fetch(
+    plugin: &CloudPlugin,
+    engine: &EngineInterface,
+    url: &Spanned<String>,
+    version: Option<&str>,
+    settings: &ClientSettings,
+    progress: &Progress,
+    call_span: Span,
+) -> Result<Bytes, Box<ShellError>> {
+    let url = parse_url_arg(engine, url, call_span)?;
+    let Some(version) = version else {
+        return Ok(plugin
+            .cache
+            .get(engine, &url, settings, progress, call_span)
+            .await?);
+    };
+
+    // Old versions never change, but are rarely read more than once, so they are not cached
+    let (object_store, path) = plugin.parse_url(engine, &url, settings, call_span).await?;
+    if !object_store.supports_versions() {
+        return Err(Box::new(versions_unsupported(url.span)));
+    }
+    let opts = GetOptions {
+        version: Some(version.to_string()),
+        ..GetOptions::default()
+    };
+    let fetch_error = |e| CloudError::new(READ_ERROR, e, object_store.provider(), url.span);
+    let result = object_store
+        .object_store()
+        .get_opts(&path, opts)
+        .await
+        .map_err(fetch_error)?;
+    Ok(read_body(result, progress).await.map_err(fetch_error)?)
+}
+
+/// Reads an object whose key object_store can't represent. Such objects aren't cached.
+async fn fetch_raw(
+    plugin: &CloudPlugin,
+    engine: &EngineInterface,
+    raw_key: &RawKey,
+    opts: &GetOptions,
+    settings: &ClientSettings,
+    call_span: Span,
+) -> Result<Bytes, Box<ShellError>> {
+    let object_store = raw_key
+        .object_store(plugin, engine, settings, call_span)
+        .await?;
+    Ok(object_store
+        .get_raw(&raw_key.key, opts, raw_key.bucket_url.span)
+        .await?)
+}
+
+/// Converts the fetched bytes using the `from` command matching the file extension
+fn convert(
+    engine: &EngineInterface,
+    call: &nu_plugin::EvaluatedCall,
+    url: &Spanned<String>,
+    bytes: Bytes,
+    raw: bool,
+) -> Result<PipelineData, Box<ShellError>> {
+    let call_span = call.head;
+    let path = Path::new(&url.item);
 
     let content_type = if raw {
         path.extension()
@@ -137,7 +576,7 @@ async fn command(
     }
 }
 
-fn detect_content_type(extension: &str) -> Option<String> {
+pub(crate) fn detect_content_type(extension: &str) -> Option<String> {
     // This will allow the overriding of metadata to be consistent with
     // the content type
     match extension {
@@ -0,0 +1,159 @@
+use std::time::Duration;
+
+use crate::{CloudPlugin, config::PluginConfig, error::CloudError};
+use chrono::Utc;
+use http::Method;
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
+use nu_protocol::{
+    Category, Example, LabeledError, PipelineData, ShellError, Signature, Spanned, SyntaxShape,
+    Type, Value, record,
+};
+
+use super::{apply_literal_key, literal_key_flag, parse_url_arg, resolve_alias};
+
+const DEFAULT_EXPIRES: Duration = Duration::from_secs(60 * 60);
+
+/// The longest time S3 signed urls (SigV4) can be valid for
+const MAX_EXPIRES: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+pub struct Presign;
+
+impl PluginCommand for Presign {
+    type Plugin = CloudPlugin;
+
+    fn name(&self) -> &str {
+        "cloud presign"
+    }
+
+    fn signature(&self) -> nu_protocol::Signature {
+        literal_key_flag(Signature::build("cloud presign"))
+            .input_output_types(vec![(Type::Nothing, Type::record())])
+            .required("uri", SyntaxShape::String, "The file url to sign.")
+            .named(
+                "expires",
+                SyntaxShape::Duration,
+                "How long the signed url is valid for, at most 7 days. Defaults to 1hr.",
+                Some('e'),
+            )
+            .named(
+                "method",
+                SyntaxShape::String,
+                "The http method the url is signed for, GET or PUT. Defaults to GET.",
+                Some('m'),
+            )
+            .category(Category::FileSystem)
+    }
+
+    fn description(&self) -> &str {
+        "Generate a time limited presigned url for downloading or uploading a file."
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![
+            Example {
+                description: "Create a url to download a file from s3 that is valid for one hour.",
+                example: "cloud presign s3://mybucket/file.txt",
+                result: None,
+            },
+            Example {
+                description: "Create a url to upload a file to s3 that is valid for ten minutes.",
+                example: "cloud presign --method PUT --expires 10min s3://mybucket/file.txt",
+                result: None,
+            },
+        ]
+    }
+
+    fn run(
+        &self,
+        plugin: &Self::Plugin,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        _input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        plugin
+            .rt
+            .block_on(command(engine, plugin, call))
+            .map_err(LabeledError::from)
+    }
+}
+
+async fn command(
+    engine: &EngineInterface,
+    plugin: &CloudPlugin,
+    call: &EvaluatedCall,
+) -> Result<PipelineData, ShellError> {
+    let call_span = call.head;
+    // Signing makes no requests, the settings only select the cached store
+    let settings = PluginConfig::load(engine).map_err(|e| *e)?.client;
+    let url = resolve_alias(engine, call.req(0)?).map_err(|e| *e)?;
+    let url = apply_literal_key(call, url).map_err(|e| *e)?;
+    let url = parse_url_arg(engine, &url, call_span).map_err(|e| *e)?;
+
+    let expires = match call.get_flag::<Spanned<Duration>>("expires")? {
+        Some(expires) if expires.item > MAX_EXPIRES => {
+            return Err(ShellError::LabeledError(Box::new(
+                LabeledError::new("Invalid expiry: signed urls are valid for at most 7 days")
+                    .with_label("longer than 7 days", expires.span)
+                    .with_help("Use an expiry of 7day or less, i.e. --expires 1day"),
+            )));
+        }
+        Some(expires) => expires.item,
+        None => DEFAULT_EXPIRES,
+    };
+    let method = match call.get_flag::<Spanned<String>>("method")? {
+        None => Method::GET,
+        Some(method) => match method.item.to_uppercase().as_str() {
+            "GET" => Method::GET,
+            "PUT" => Method::PUT,
+            _ => {
+                return Err(ShellError::IncorrectValue {
+                    msg: format!("Unsupported method {}, expected GET or PUT", method.item),
+                    val_span: method.span,
+                    call_span,
+                });
+            }
+        },
+    };
+
+    let (object_store, path) = plugin.parse_url(engine, &url, &settings, call_span).await?;
+    let signer = object_store
+        .signer()
+        .ok_or_else(|| ShellError::GenericError {
+            error: format!("Cannot presign {} urls", url.item.scheme()),
+            msg: "this storage type does not support signed urls".into(),
+            span: Some(url.span),
+            help: Some("Presigned urls are only supported for cloud storage such as s3".into()),
+            inner: vec![],
+        })?;
+
+    let signed_url = signer
+        .signed_url(method.clone(), &path, expires)
+        .await
+        .map_err(|e| {
+            CloudError::new(
+                "Could not presign url",
+                e,
+                object_store.provider(),
+                url.span,
+            )
+        })?;
+
+    let expires_at = Utc::now()
+        + chrono::Duration::from_std(expires).map_err(|e| ShellError::IncorrectValue {
+            msg: format!("Invalid expiry: {e}"),
+            val_span: call.get_flag_span("expires").unwrap_or(call_span),
+            call_span,
+        })?;
+
+    Ok(PipelineData::Value(
+        Value::record(
+            record!(
+                "url" => Value::string(signed_url.to_string(), call_span),
+                "method" => Value::string(method.to_string(), call_span),
+                "expires" => Value::date(expires_at.fixed_offset(), call_span),
+            ),
+            call_span,
+        ),
+        None,
+    ))
+}
@@ -0,0 +1,97 @@
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
+use nu_protocol::{
+    Category, Example, LabeledError, PipelineData, ShellError, Signature, Spanned, SyntaxShape,
+    Type, Value, record,
+};
+
+use crate::{CloudPlugin, providers::versions_unsupported};
+
+use super::{
+    apply_literal_key, client_flags, client_settings, literal_key_flag, parse_url_arg,
+    resolve_alias,
+};
+
+pub struct Restore;
+
+impl PluginCommand for Restore {
+    type Plugin = CloudPlugin;
+
+    fn name(&self) -> &str {
+        "cloud restore"
+    }
+
+    fn signature(&self) -> nu_protocol::Signature {
+        client_flags(literal_key_flag(Signature::build("cloud restore")))
+            .input_output_types(vec![(Type::Nothing, Type::record())])
+            .required("uri", SyntaxShape::String, "The file url to restore.")
+            .required_named(
+                "version",
+                SyntaxShape::String,
+                "The version of the file to make the latest version",
+                None,
+            )
+            .category(Category::FileSystem)
+    }
+
+    fn description(&self) -> &str {
+        "Restore an older version of a file in a versioned bucket by copying it over the latest version. S3 copies versions of at most 5 GiB."
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![Example {
+            description: "Restore an older version of a file in s3.",
+            example: "cloud restore --version 3HL4kqtJlcpXroDTDmJ.rmSpXd3dIbrHY s3://mybucket/file.txt",
+            result: None,
+        }]
+    }
+
+    fn run(
+        &self,
+        plugin: &Self::Plugin,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        _input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        plugin
+            .rt
+            .block_on(command(engine, plugin, call))
+            .map_err(|e| LabeledError::from(*e))
+    }
+}
+
+async fn command(
+    engine: &EngineInterface,
+    plugin: &CloudPlugin,
+    call: &EvaluatedCall,
+) -> Result<PipelineData, Box<ShellError>> {
+    let call_span = call.head;
+    let settings = client_settings(engine, call)?;
+    let url = apply_literal_key(call, resolve_alias(engine, call.req(0)?)?)?;
+    let url = parse_url_arg(engine, &url, call_span)?;
+    let version: Spanned<String> =
+        call.get_flag("version")?
+            .ok_or_else(|| ShellError::MissingParameter {
+                param_name: "version".into(),
+                span: call_span,
+            })?;
+
+    let (object_store, path) = plugin.parse_url(engine, &url, &settings, call_span).await?;
+    if !object_store.supports_versions() {
+        return Err(Box::new(versions_unsupported(url.span)));
+    }
+    let new_version = object_store
+        .restore_version(&path, &version.item, version.span)
+        .await?;
+
+    Ok(PipelineData::Value(
+        Value::record(
+            record!(
+                "url" => Value::string(url.item.to_string(), call_span),
+                "restored_version" => Value::string(version.item, call_span),
+                "version" => new_version.map(|v| Value::string(v, call_span)).unwrap_or(Value::nothing(call_span)),
+            ),
+            call_span,
+        ),
+        None,
+    ))
+}
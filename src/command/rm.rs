@@ -1,14 +1,26 @@
-use std::{path::PathBuf, str::FromStr, vec};
-
-use nu_plugin::{EngineInterface, PluginCommand};
+use futures::{StreamExt, TryStreamExt, future, stream, stream::BoxStream};
+use nu_plugin::{DynamicCompletionCall, EngineInterface, PluginCommand};
 use nu_protocol::{
-    Category, Example, LabeledError, PipelineData, ShellError, Signature, Spanned, SyntaxShape,
-    Type,
+    Category, DynamicSuggestion, Example, LabeledError, NuGlob, PipelineData, ShellError,
+    Signature, Span, Spanned, SyntaxShape, Type, Value, engine::ArgType, record,
+};
+use object_store::{ObjectStoreExt, path::Path};
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
 };
-use object_store::ObjectStoreExt;
 use url::Url;
 
-use crate::CloudPlugin;
+use crate::{
+    CloudPlugin,
+    error::CloudError,
+    providers::{ClientSettings, NuObjectStore, object_url, store_root_url, versions_unsupported},
+};
+
+use super::{
+    RawKey, client_flags, client_settings, complete::complete_url, glob::expand_url, input_urls,
+    literal_key_flag, literal_url, parse_url_arg, raw_s3_key, resolve_glob_alias,
+};
 
 pub struct Remove;
 
@@ -20,22 +32,64 @@ impl PluginCommand for Remove {
     }
 
     fn signature(&self) -> nu_protocol::Signature {
-        Signature::build("cloud rm")
-            .input_output_types(vec![(Type::Any, Type::Nothing)])
-            .required("uri", SyntaxShape::String, "The file url to use.")
+        client_flags(literal_key_flag(Signature::build("cloud rm")))
+            .input_output_types(vec![(Type::Any, Type::table())])
+            .rest(
+                "uri",
+                SyntaxShape::GlobPattern,
+                "The file urls or glob patterns to use. If omitted, the urls are taken from the pipeline input.",
+            )
+            .switch(
+                "recursive",
+                "Remove every object below the given urls",
+                Some('r'),
+            )
+            .switch(
+                "dry-run",
+                "List the objects that would be removed without removing them",
+                Some('n'),
+            )
+            .named(
+                "version",
+                SyntaxShape::String,
+                "Permanently remove the given version of a file in a versioned bucket",
+                None,
+            )
             .category(Category::FileSystem)
     }
 
     fn description(&self) -> &str {
-        "Remove a file from cloud sotrage"
+        "Remove files from cloud storage"
     }
 
     fn examples(&self) -> Vec<Example<'_>> {
-        vec![Example {
-            description: "Remove a file from s3.",
-            example: "cloud rm s3://mybucket/file.txt",
-            result: None,
-        }]
+        vec![
+            Example {
+                description: "Remove a file from s3.",
+                example: "cloud rm s3://mybucket/file.txt",
+                result: None,
+            },
+            Example {
+                description: "Remove every json file below a prefix in s3.",
+                example: "cloud rm s3://mybucket/logs/**/*.json",
+                result: None,
+            },
+            Example {
+                description: "Show which objects below a prefix would be removed.",
+                example: "cloud rm --recursive --dry-run s3://mybucket/logs",
+                result: None,
+            },
+            Example {
+                description: "Permanently remove a single version of a file in a versioned s3 bucket.",
+                example: "cloud rm --version 3HL4kqtJlcpXroDTDmJ.rmSpXd3dIbrHY s3://mybucket/file.txt",
+                result: None,
+            },
+            Example {
+                description: "Remove the files listed by cloud ls.",
+                example: "cloud ls s3://mybucket/logs | where size == 0b | cloud rm",
+                result: None,
+            },
+        ]
     }
 
     fn run(
@@ -43,48 +97,282 @@ impl PluginCommand for Remove {
         plugin: &Self::Plugin,
         engine: &EngineInterface,
         call: &nu_plugin::EvaluatedCall,
-        _input: PipelineData,
+        input: PipelineData,
     ) -> Result<PipelineData, LabeledError> {
         plugin
             .rt
-            .block_on(command(engine, plugin, call))
-            .map_err(LabeledError::from)
+            .block_on(command(engine, plugin, call, input))
+            .map_err(|e| LabeledError::from(*e))
+    }
+
+    #[expect(deprecated, reason = "completion is still experimental in nushell")]
+    fn get_dynamic_completion(
+        &self,
+        plugin: &Self::Plugin,
+        engine: &EngineInterface,
+        call: DynamicCompletionCall,
+        arg_type: ArgType,
+        _experimental: nu_protocol::engine::ExperimentalMarker,
+    ) -> Option<Vec<DynamicSuggestion>> {
+        match arg_type {
+            ArgType::Positional(_) => complete_url(plugin, engine, &call),
+            ArgType::Flag(_) => None,
+        }
     }
 }
 
+/// The objects to delete from a single store
+struct Deletion {
+    /// Url of the root of the store
+    root: Url,
+    /// Span of the url argument the objects were found with
+    span: Span,
+    object_store: NuObjectStore,
+    /// The listings of the urls, which are only consumed while deleting
+    locations: Vec<BoxStream<'static, object_store::Result<Path>>>,
+}
+
 async fn command(
     engine: &EngineInterface,
     plugin: &CloudPlugin,
     call: &nu_plugin::EvaluatedCall,
-) -> Result<PipelineData, ShellError> {
+    input: PipelineData,
+) -> Result<PipelineData, Box<ShellError>> {
     let call_span = call.head;
-    let url_path: Spanned<PathBuf> = call.req(0)?;
-    let url = url_path
-        .item
-        .to_str()
-        .expect("The path should already be unicode")
-        .to_string();
-    let url = Spanned {
-        item: Url::from_str(&url).map_err(|e| ShellError::IncorrectValue {
-            msg: format!("Invalid Url: {e}"),
-            val_span: url_path.span,
-            call_span,
-        })?,
-        span: url_path.span,
+    let recursive = call.has_flag("recursive")?;
+    let dry_run = call.has_flag("dry-run")?;
+    let settings = client_settings(engine, call)?;
+    let literal_key = call.has_flag("literal-key")?;
+    let version: Option<Spanned<String>> = call.get_flag("version")?;
+    if recursive && let Some(version) = &version {
+        return Err(Box::new(ShellError::IncompatibleParameters {
+            left_message: "can't use --version".into(),
+            left_span: version.span,
+            right_message: "together with --recursive".into(),
+            right_span: call.get_flag_span("recursive").unwrap_or(call_span),
+        }));
+    }
+
+    let args: Vec<Spanned<NuGlob>> = call.rest(0)?;
+    let urls = if args.is_empty() {
+        input_urls(engine, input, call_span)?
+    } else {
+        let mut urls = vec![];
+        for arg in args {
+            let arg = resolve_glob_alias(engine, arg)?;
+            if literal_key {
+                let arg = Spanned {
+                    item: arg.item.as_ref().to_string(),
+                    span: arg.span,
+                };
+                match raw_s3_key(&arg) {
+                    Some(_) if !recursive => urls.push(arg),
+                    _ => urls.push(literal_url(&arg, call_span)?),
+                }
+            } else {
+                urls.extend(expand_url(plugin, engine, arg, &settings, call_span).await?);
+            }
+        }
+        urls
     };
-    let (object_store, path) = plugin.parse_url(engine, &url, call_span).await?;
-
-    object_store
-        .object_store()
-        .delete(&path)
-        .await
-        .map_err(|e| ShellError::GenericError {
-            error: format!("Could not delete delete from cloud storage: {e}"),
-            msg: "".into(),
-            span: Some(call_span),
-            help: None,
-            inner: vec![],
-        })?;
-
-    Ok(PipelineData::empty())
+    if urls.is_empty() {
+        return Err(Box::new(ShellError::MissingParameter {
+            param_name: "uri".into(),
+            span: call_span,
+        }));
+    }
+
+    // Keys that object_store can't represent are deleted with the S3 sdk, see [`RawKey`]
+    let urls: Vec<(Spanned<String>, Option<RawKey>)> = urls
+        .into_iter()
+        .map(|url| {
+            let raw_key = (literal_key && !recursive)
+                .then(|| raw_s3_key(&url))
+                .flatten();
+            (url, raw_key)
+        })
+        .collect();
+
+    if let Some(version) = version {
+        return remove_version(engine, plugin, urls, version, &settings, dry_run, call_span).await;
+    }
+
+    // Group the objects by store and argument, so that each store can delete the objects
+    // matching a glob pattern in batches
+    let mut deletions: Vec<Deletion> = vec![];
+    let mut raw_deletions = vec![];
+    for (url, raw_key) in urls {
+        if let Some(raw_key) = raw_key {
+            let object_store = raw_key
+                .object_store(plugin, engine, &settings, call_span)
+                .await?;
+            raw_deletions.push((url, object_store, raw_key.key));
+            continue;
+        }
+        let url = parse_url_arg(engine, &url, call_span)?;
+        let (object_store, path) = plugin.parse_url(engine, &url, &settings, call_span).await?;
+        let root = store_root_url(&url.item, &path);
+
+        let locations = if recursive {
+            list_locations(&object_store, path)
+        } else {
+            stream::iter([Ok(path)]).boxed()
+        };
+
+        match deletions
+            .iter_mut()
+            .find(|d| d.root == root && d.span == url.span)
+        {
+            Some(deletion) => deletion.locations.push(locations),
+            None => deletions.push(Deletion {
+                root,
+                span: url.span,
+                object_store,
+                locations: vec![locations],
+            }),
+        }
+    }
+
+    let mut rows = vec![];
+    for deletion in deletions {
+        let signals = engine.signals().clone();
+        let locations = stream::iter(deletion.locations)
+            .flatten()
+            .take_while(move |_| future::ready(!signals.interrupted()))
+            .boxed();
+        let (results, status): (Vec<_>, _) = if dry_run {
+            (locations.collect().await, "dry run")
+        } else {
+            let results = deletion
+                .object_store
+                .object_store()
+                .delete_stream(locations)
+                .collect()
+                .await;
+            (results, "deleted")
+        };
+
+        rows.extend(results.into_iter().map(|result| {
+            match result {
+                Ok(location) => {
+                    result_row(object_url(&deletion.root, &location), status, deletion.span)
+                }
+                Err(e) => Value::error(
+                    CloudError::new(
+                        "Could not delete file",
+                        e,
+                        deletion.object_store.provider(),
+                        deletion.span,
+                    )
+                    .into(),
+                    deletion.span,
+                ),
+            }
+        }));
+    }
+
+    for (url, object_store, key) in raw_deletions {
+        if dry_run {
+            rows.push(result_row(url.item, "dry run", url.span));
+            continue;
+        }
+        match object_store.delete_raw(&key, None, url.span).await {
+            Ok(()) => rows.push(result_row(url.item, "deleted", url.span)),
+            Err(e) => rows.push(Value::error(e, url.span)),
+        }
+    }
+
+    Ok(PipelineData::Value(Value::list(rows, call_span), None))
+}
+
+/// Permanently removes a single version of each url
+async fn remove_version(
+    engine: &EngineInterface,
+    plugin: &CloudPlugin,
+    urls: Vec<(Spanned<String>, Option<RawKey>)>,
+    version: Spanned<String>,
+    settings: &ClientSettings,
+    dry_run: bool,
+    call_span: Span,
+) -> Result<PipelineData, Box<ShellError>> {
+    let mut rows = vec![];
+    for (url, raw_key) in urls {
+        if let Some(raw_key) = raw_key {
+            let object_store = raw_key
+                .object_store(plugin, engine, settings, call_span)
+                .await?;
+            if dry_run {
+                rows.push(result_row(url.item, "dry run", url.span));
+                continue;
+            }
+            match object_store
+                .delete_raw(&raw_key.key, Some(&version.item), version.span)
+                .await
+            {
+                Ok(()) => rows.push(result_row(url.item, "deleted", url.span)),
+                Err(e) => rows.push(Value::error(e, url.span)),
+            }
+            continue;
+        }
+        let url = parse_url_arg(engine, &url, call_span)?;
+        let (object_store, path) = plugin.parse_url(engine, &url, settings, call_span).await?;
+        if !object_store.supports_versions() {
+            return Err(Box::new(versions_unsupported(url.span)));
+        }
+
+        let root = store_root_url(&url.item, &path);
+        if dry_run {
+            rows.push(result_row(object_url(&root, &path), "dry run", url.span));
+            continue;
+        }
+        match object_store
+            .delete_version(&path, &version.item, version.span)
+            .await
+        {
+            Ok(()) => rows.push(result_row(object_url(&root, &path), "deleted", url.span)),
+            Err(e) => rows.push(Value::error(e, url.span)),
+        }
+    }
+    Ok(PipelineData::Value(Value::list(rows, call_span), None))
+}
+
+/// Lists every object below path. If there are none, path is assumed to be a single object
+/// and listed if it exists, or returns its not found error.
+fn list_locations(
+    object_store: &NuObjectStore,
+    path: Path,
+) -> BoxStream<'static, object_store::Result<Path>> {
+    let found = Arc::new(AtomicBool::new(false));
+    let listing = {
+        let found = found.clone();
+        object_store
+            .object_store()
+            .list(Some(&path))
+            .map_ok(move |meta| {
+                found.store(true, Ordering::Relaxed);
+                meta.location
+            })
+    };
+    let object_store = object_store.clone();
+    let single = stream::once(async move {
+        if found.load(Ordering::Relaxed) {
+            return None;
+        }
+        match object_store.object_store().head(&path).await {
+            Ok(_) => Some(Ok(path)),
+            Err(e) => Some(Err(e)),
+        }
+    })
+    .filter_map(future::ready);
+    listing.chain(single).boxed()
+}
+
+fn result_row(url: impl Into<String>, status: &str, span: Span) -> Value {
+    Value::record(
+        record!(
+            "url" => Value::string(url, span),
+            "status" => Value::string(status, span),
+        ),
+        span,
+    )
 }
@@ -1,22 +1,41 @@
 use std::{
     io::{ErrorKind, Read},
-    path::PathBuf,
-    str::FromStr,
+    time::{Duration, Instant},
     vec,
 };
 
 use bytes::Bytes;
-use log::debug;
-use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
+use log::{debug, warn};
+use nu_plugin::{DynamicCompletionCall, EngineInterface, EvaluatedCall, PluginCommand};
 use nu_protocol::{
-    ByteStreamSource, Category, Example, LabeledError, ListStream, PipelineData, ShellError,
-    Signals, Signature, Span, Spanned, SyntaxShape, Type, Value, process::ChildPipe,
+    ByteStreamSource, Category, DynamicSuggestion, Example, Filesize, LabeledError, ListStream,
+    PipelineData, PipelineMetadata, ShellError, Signals, Signature, Span, Spanned, SyntaxShape,
+    Type, Value,
+    engine::ArgType,
+    process::{ChildPipe, ChildProcess},
+    record,
     shell_error::io::IoError,
 };
-use object_store::{ObjectStoreExt, PutPayload, WriteMultipart};
-use url::Url;
+use object_store::{
+    Attribute, Attributes, PutMode, PutMultipartOptions, PutOptions, PutPayload, PutResult,
+    UpdateVersion, path::Path,
+};
+
+use crate::{
+    CloudPlugin,
+    error::CloudError,
+    progress::Progress,
+    providers::{NuObjectStore, Provider},
+};
 
-use crate::CloudPlugin;
+use super::{
+    apply_literal_key, client_flags, client_settings,
+    complete::complete_url,
+    literal_key_flag,
+    multipart::{DEFAULT_CONCURRENCY, MIN_PART_SIZE, PartWriter},
+    open::detect_content_type,
+    parse_url_arg, progress_enabled, raw_s3_key, resolve_alias,
+};
 
 pub struct Save;
 
@@ -28,19 +47,119 @@ impl PluginCommand for Save {
     }
 
     fn signature(&self) -> nu_protocol::Signature {
-        Signature::build("cloud save")
-            .input_output_types(vec![(Type::Any, Type::Nothing)])
+        client_flags(literal_key_flag(Signature::build("cloud save")))
+            .input_output_types(vec![
+                (Type::Any, Type::Nothing),
+                (Type::Any, Type::record()),
+            ])
             .required("uri", SyntaxShape::String, "The file url to use.")
             .switch("raw", "save file as raw binary", Some('r'))
+            .switch(
+                "result",
+                "return a record with the url, size, etag, version and upload time of the file",
+                None,
+            )
+            .switch(
+                "progress",
+                "report upload progress on stderr, the default when stderr is a terminal",
+                Some('p'),
+            )
+            .switch("quiet", "don't report upload progress", Some('q'))
+            .switch(
+                "no-clobber",
+                "fail instead of overwriting the file if it already exists",
+                Some('n'),
+            )
+            .switch("if-not-exists", "same as --no-clobber", None)
+            .named(
+                "if-match",
+                SyntaxShape::String,
+                "only overwrite the file if its etag still matches the given etag",
+                None,
+            )
+            .named(
+                "part-size",
+                SyntaxShape::Filesize,
+                "the size of the parts of streamed uploads, at least 5MiB. Grows automatically for very large uploads",
+                None,
+            )
+            .named(
+                "concurrency",
+                SyntaxShape::Int,
+                "the number of parts of streamed uploads to upload at the same time. Defaults to 8",
+                None,
+            )
+            .named(
+                "content-type",
+                SyntaxShape::String,
+                "the content type of the file. Inferred from the file extension when omitted",
+                None,
+            )
+            .named(
+                "cache-control",
+                SyntaxShape::String,
+                "the cache control policy of the file, i.e. 'max-age=3600'",
+                None,
+            )
+            .named(
+                "content-encoding",
+                SyntaxShape::String,
+                "the encodings applied to the file, i.e. gzip",
+                None,
+            )
+            .named(
+                "content-disposition",
+                SyntaxShape::String,
+                "how browsers should handle the file, i.e. 'attachment; filename=\"report.csv\"'",
+                None,
+            )
+            .named(
+                "metadata",
+                SyntaxShape::Record(vec![]),
+                "custom metadata to store with the file",
+                Some('m'),
+            )
             .category(Category::FileSystem)
     }
 
     fn examples(&self) -> Vec<Example<'_>> {
-        vec![Example {
-            description: "Save a csv file to s3.",
-            example: "[[a b]; [1 1] [1 2] [2 1] [2 2] [3 1] [3 2]] | to csv | cloud save s3://mybucket/file.csv",
-            result: None,
-        }]
+        vec![
+            Example {
+                description: "Save a csv file to s3.",
+                example: "[[a b]; [1 1] [1 2] [2 1] [2 2] [3 1] [3 2]] | to csv | cloud save s3://mybucket/file.csv",
+                result: None,
+            },
+            Example {
+                description: "Stream a database dump to s3 in 64MiB parts, uploading four parts at a time.",
+                example: "^pg_dump mydb | cloud save --part-size 64MiB --concurrency 4 s3://mybucket/mydb.sql",
+                result: None,
+            },
+            Example {
+                description: "Save a file to s3 and get its new etag and version.",
+                example: "open data.json | cloud save --result s3://mybucket/data.json",
+                result: None,
+            },
+            Example {
+                description: "Save a gzipped web page to s3 that browsers may cache for an hour.",
+                example: "open index.html.gz | cloud save --content-type text/html --content-encoding gzip --cache-control 'max-age=3600' s3://mybucket/index.html",
+                result: None,
+            },
+            Example {
+                description: "Create a lock file in s3, failing if another job already created it.",
+                example: "'job-1' | cloud save --no-clobber s3://mybucket/job.lock",
+                result: None,
+            },
+            Example {
+                description: "Update a shared state file only if no other job changed it since it was listed.",
+                example: "let etag = cloud ls s3://mybucket/state.json | get 0.etag; {count: 2} | cloud save --if-match $etag s3://mybucket/state.json",
+                result: None,
+            },
+            Example {
+                description: "Save a file to s3 with custom metadata.",
+                example: "open report.pdf | cloud save --metadata {owner: finance} s3://mybucket/report.pdf",
+                result: None,
+            },
+        ]
     }
 
     fn description(&self) -> &str {
@@ -59,6 +178,21 @@ impl PluginCommand for Save {
             .block_on(command(plugin, engine, call, input))
             .map_err(|e| LabeledError::from(*e))
     }
+
+    #[expect(deprecated, reason = "completion is still experimental in nushell")]
+    fn get_dynamic_completion(
+        &self,
+        plugin: &Self::Plugin,
+        engine: &EngineInterface,
+        call: DynamicCompletionCall,
+        arg_type: ArgType,
+        _experimental: nu_protocol::engine::ExperimentalMarker,
+    ) -> Option<Vec<DynamicSuggestion>> {
+        match arg_type {
+            ArgType::Positional(_) => complete_url(plugin, engine, &call),
+            ArgType::Flag(_) => None,
+        }
+    }
 }
 
 async fn command(
@@ -69,141 +203,407 @@ async fn command(
 ) -> Result<PipelineData, Box<ShellError>> {
     let raw = call.has_flag("raw")?;
     let call_span = call.head;
-    let url_path: Spanned<PathBuf> = call.req(0)?;
-    let url = url_path
-        .item
-        .to_str()
-        .expect("The path should already be unicode")
-        .to_string();
-    let url = Spanned {
-        item: Url::from_str(&url).map_err(|e| ShellError::IncorrectValue {
-            msg: format!("Invalid Url: {e}"),
-            val_span: url_path.span,
-            call_span,
-        })?,
-        span: url_path.span,
+    let settings = client_settings(engine, call)?;
+    let arg = resolve_alias(engine, call.req(0)?)?;
+    // Keys that object_store can't represent are written with the S3 sdk, see [`RawKey`]
+    let raw_key = match call.has_flag("literal-key")? {
+        true => raw_s3_key(&arg),
+        false => None,
+    };
+    let url = match &raw_key {
+        Some(raw_key) => raw_key.bucket_url.clone(),
+        None => apply_literal_key(call, arg.clone())?,
+    };
+    let url = parse_url_arg(engine, &url, call_span)?;
+    let (display_url, key_path) = match &raw_key {
+        Some(raw_key) => (arg.item, std::path::Path::new(&raw_key.key)),
+        None => (url.item.to_string(), std::path::Path::new(url.item.path())),
     };
 
-    match input {
-        PipelineData::ByteStream(stream, _metadata) => {
-            debug!("Handling byte stream");
-
-            match stream.into_source() {
-                ByteStreamSource::Read(read) => {
-                    bytestream_to_cloud(plugin, engine, read, &url, call_span).await?;
-                }
-                ByteStreamSource::File(source) => {
-                    bytestream_to_cloud(plugin, engine, source, &url, call_span).await?;
+    let (object_store, path) = plugin.parse_url(engine, &url, &settings, call_span).await?;
+    let extension = match &raw_key {
+        Some(_) => key_path.extension().and_then(|ext| ext.to_str()),
+        None => path.extension(),
+    };
+    let attributes = put_attributes(call, &object_store, extension, input.metadata().as_ref())?;
+    let target = Target {
+        object_store,
+        path,
+        raw_key: raw_key.as_ref().map(|raw_key| raw_key.key.clone()),
+        attributes,
+        mode: put_mode(call)?,
+        part_size: part_size(call)?,
+        concurrency: concurrency(call)?,
+        span: url.span,
+    };
+    let started = Instant::now();
+    let progress = Progress::new(format!("Uploading {display_url}"), progress_enabled(call)?);
+
+    // Multipart uploads can't be conditional, so conditional writes are buffered and
+    // uploaded with a single put. The S3 sdk only puts raw keys at once as well.
+    let upload = if !matches!(target.mode, PutMode::Overwrite) || target.raw_key.is_some() {
+        let bytes = match input {
+            PipelineData::ByteStream(stream, _metadata) => stream.into_bytes()?,
+            PipelineData::ListStream(ls, _pipeline_metadata) if raw => {
+                let mut bytes = vec![];
+                for v in ls {
+                    engine.signals().check(&call_span)?;
+                    bytes.extend(value_to_bytes(v)?);
                 }
-                ByteStreamSource::Child(mut child) => {
-                    if let Some(stdout) = child.stdout.take() {
-                        let res = match stdout {
-                            ChildPipe::Pipe(pipe) => {
-                                bytestream_to_cloud(plugin, engine, pipe, &url, call_span).await
-                            }
-                            ChildPipe::Tee(tee) => {
-                                bytestream_to_cloud(plugin, engine, tee, &url, call_span).await
-                            }
-                        };
-                        res?;
-                    };
+                bytes
+            }
+            input => input_to_bytes(input, key_path, raw, engine, call, call_span)?,
+        };
+        Some(stream_bytes(bytes, target, &progress).await?)
+    } else {
+        match input {
+            PipelineData::ByteStream(stream, _metadata) => {
+                debug!("Handling byte stream");
+
+                let size_hint = stream.known_size();
+                match stream.into_source() {
+                    ByteStreamSource::Read(read) => Some(
+                        stream_to_cloud(
+                            engine, read, None, size_hint, target, &progress, call_span,
+                        )
+                        .await?,
+                    ),
+                    ByteStreamSource::File(source) => {
+                        let size_hint =
+                            size_hint.or_else(|| source.metadata().ok().map(|meta| meta.len()));
+                        Some(
+                            stream_to_cloud(
+                                engine, source, None, size_hint, target, &progress, call_span,
+                            )
+                            .await?,
+                        )
+                    }
+                    ByteStreamSource::Child(mut child) => match child.stdout.take() {
+                        Some(ChildPipe::Pipe(pipe)) => Some(
+                            stream_to_cloud(
+                                engine,
+                                pipe,
+                                Some(*child),
+                                size_hint,
+                                target,
+                                &progress,
+                                call_span,
+                            )
+                            .await?,
+                        ),
+                        Some(ChildPipe::Tee(tee)) => Some(
+                            stream_to_cloud(
+                                engine,
+                                tee,
+                                Some(*child),
+                                size_hint,
+                                target,
+                                &progress,
+                                call_span,
+                            )
+                            .await?,
+                        ),
+                        None => {
+                            child.wait()?;
+                            None
+                        }
+                    },
                 }
             }
+            PipelineData::ListStream(ls, _pipeline_metadata) if raw => {
+                debug!("Handling list stream");
+                Some(liststream_to_cloud(engine, ls, target, &progress, call_span).await?)
+            }
+            input => {
+                debug!("Handling input");
+                let bytes = input_to_bytes(input, key_path, raw, engine, call, call_span)?;
+                Some(stream_bytes(bytes, target, &progress).await?)
+            }
+        }
+    };
+
+    match upload {
+        Some(upload) if call.has_flag("result")? => Ok(PipelineData::Value(
+            upload.into_value(&display_url, started.elapsed(), call_span),
+            None,
+        )),
+        _ => Ok(PipelineData::empty()),
+    }
+}
+
+/// The outcome of an upload, returned when --result is given
+struct Upload {
+    result: PutResult,
+    size: u64,
+    multipart: bool,
+}
+
+impl Upload {
+    fn into_value(self, url: &str, elapsed: Duration, span: Span) -> Value {
+        Value::record(
+            record!(
+                "url" => Value::string(url, span),
+                "size" => Value::filesize(self.size as i64, span),
+                "etag" => self.result.e_tag.map(|s| Value::string(s, span)).unwrap_or(Value::nothing(span)),
+                "version" => self.result.version.map(|s| Value::string(s, span)).unwrap_or(Value::nothing(span)),
+                "elapsed" => Value::duration(elapsed.as_nanos() as i64, span),
+                "multipart" => Value::bool(self.multipart, span),
+            ),
+            span,
+        )
+    }
+}
+
+/// The object being saved
+struct Target {
+    object_store: NuObjectStore,
+    path: Path,
+    /// The exact key to write instead of path, see [`RawKey`]
+    raw_key: Option<String>,
+    attributes: Attributes,
+    mode: PutMode,
+    /// The minimum size of the parts of a multipart upload
+    part_size: usize,
+    /// The number of parts of a multipart upload that are uploaded at the same time
+    concurrency: usize,
+    /// Span of the url argument
+    span: Span,
+}
 
-            Ok(PipelineData::Empty)
+fn part_size(call: &EvaluatedCall) -> Result<usize, Box<ShellError>> {
+    match call.get_flag::<Spanned<Filesize>>("part-size")? {
+        Some(size) if size.item.get() < MIN_PART_SIZE as i64 => {
+            Err(Box::new(ShellError::IncorrectValue {
+                msg: "part-size must be at least 5MiB".into(),
+                val_span: size.span,
+                call_span: call.head,
+            }))
         }
-        PipelineData::ListStream(ls, _pipeline_metadata) if raw => {
-            debug!("Handling list stream");
-            liststream_to_cloud(plugin, engine, ls, &url, call_span).await?;
-            Ok(PipelineData::empty())
+        Some(size) => Ok(size.item.get() as usize),
+        None => Ok(MIN_PART_SIZE),
+    }
+}
+
+fn concurrency(call: &EvaluatedCall) -> Result<usize, Box<ShellError>> {
+    match call.get_flag::<Spanned<i64>>("concurrency")? {
+        Some(concurrency) if concurrency.item < 1 => Err(Box::new(ShellError::IncorrectValue {
+            msg: "concurrency must be at least 1".into(),
+            val_span: concurrency.span,
+            call_span: call.head,
+        })),
+        Some(concurrency) => Ok(concurrency.item as usize),
+        None => Ok(DEFAULT_CONCURRENCY),
+    }
+}
+
+/// Parses the --no-clobber (or --if-not-exists) and --if-match flags into the mode of the put
+fn put_mode(call: &EvaluatedCall) -> Result<PutMode, Box<ShellError>> {
+    let no_clobber_flag = match call.has_flag("no-clobber")? {
+        true => Some("no-clobber"),
+        false => call.has_flag("if-not-exists")?.then_some("if-not-exists"),
+    };
+    let no_clobber = no_clobber_flag.is_some();
+    let if_match: Option<Spanned<String>> = call.get_flag("if-match")?;
+    match if_match {
+        Some(e_tag) if no_clobber => Err(Box::new(ShellError::IncompatibleParameters {
+            left_message: "can't use --if-match".into(),
+            left_span: e_tag.span,
+            right_message: format!("together with --{}", no_clobber_flag.unwrap_or_default()),
+            right_span: no_clobber_flag
+                .and_then(|flag| call.get_flag_span(flag))
+                .unwrap_or(call.head),
+        })),
+        Some(e_tag) => Ok(PutMode::Update(UpdateVersion {
+            e_tag: Some(e_tag.item),
+            version: None,
+        })),
+        None if no_clobber => Ok(PutMode::Create),
+        None => Ok(PutMode::Overwrite),
+    }
+}
+
+/// Builds the attributes stored with the object from the command flags. Unless given,
+/// the content type is inferred from the file extension or the pipeline metadata.
+fn put_attributes(
+    call: &EvaluatedCall,
+    object_store: &NuObjectStore,
+    extension: Option<&str>,
+    metadata: Option<&PipelineMetadata>,
+) -> Result<Attributes, Box<ShellError>> {
+    let mut attributes = Attributes::new();
+
+    let content_type: Option<String> = call.get_flag("content-type")?;
+    // The local filesystem can't store attributes, so only explicitly requested ones are passed
+    let content_type = content_type.or_else(|| {
+        object_store
+            .supports_attributes()
+            .then(|| {
+                extension
+                    .and_then(detect_content_type)
+                    .or_else(|| metadata.and_then(|m| m.content_type.clone()))
+            })
+            .flatten()
+    });
+    if let Some(content_type) = content_type {
+        attributes.insert(Attribute::ContentType, content_type.into());
+    }
+
+    for (flag, attribute) in [
+        ("cache-control", Attribute::CacheControl),
+        ("content-encoding", Attribute::ContentEncoding),
+        ("content-disposition", Attribute::ContentDisposition),
+    ] {
+        if let Some(value) = call.get_flag::<String>(flag)? {
+            attributes.insert(attribute, value.into());
         }
-        input => {
-            debug!("Handling input");
-            let bytes = input_to_bytes(input, &url_path.item, raw, engine, call, call_span)?;
-            stream_bytes(plugin, engine, bytes, &url, call_span).await?;
-            Ok(PipelineData::empty())
+    }
+
+    if let Some(metadata) = call.get_flag::<Value>("metadata")? {
+        for (key, value) in metadata.into_record()? {
+            attributes.insert(
+                Attribute::Metadata(key.into()),
+                value.coerce_into_string()?.into(),
+            );
         }
     }
+
+    Ok(attributes)
 }
 
 async fn liststream_to_cloud(
-    plugin: &CloudPlugin,
     engine: &EngineInterface,
     ls: ListStream,
-    url: &Spanned<Url>,
+    target: Target,
+    progress: &Progress,
     span: Span,
-) -> Result<(), Box<ShellError>> {
+) -> Result<Upload, Box<ShellError>> {
     let signals = engine.signals();
-    let (object_store, path) = plugin.parse_url(engine, url, span).await?;
-    let upload = object_store
-        .object_store()
-        .put_multipart(&path)
-        .await
-        .unwrap();
-    let mut write = WriteMultipart::new(upload);
-
-    for v in ls {
-        signals.check(&span)?;
-        let bytes = value_to_bytes(v)?;
-        write.write(&bytes)
+    let provider = target.object_store.provider();
+    let mut write = start_multipart(target, None).await?;
+
+    let written = async {
+        let mut size = 0;
+        for v in ls {
+            signals.check(&span)?;
+            let bytes = value_to_bytes(v)?;
+            size += bytes.len() as u64;
+            progress.advance(bytes.len() as u64);
+            write
+                .write(&bytes)
+                .await
+                .map_err(|e| write_error(e, provider, span))?;
+        }
+        Ok(size)
     }
+    .await;
 
-    let _ = write.finish().await.map_err(|e| ShellError::GenericError {
-        error: format!("Could not write to S3: {e}"),
-        msg: "".into(),
-        span: None,
-        help: None,
-        inner: vec![],
-    })?;
-
-    Ok(())
+    finish_multipart(write, written, provider, progress, span).await
 }
 
-async fn bytestream_to_cloud(
-    plugin: &CloudPlugin,
+/// Uploads everything read from source. If source is the output of child, the upload is
+/// only completed if the child exits successfully.
+async fn stream_to_cloud(
     engine: &EngineInterface,
     source: impl Read,
-    url: &Spanned<Url>,
+    child: Option<ChildProcess>,
+    size_hint: Option<u64>,
+    target: Target,
+    progress: &Progress,
     span: Span,
-) -> Result<(), Box<ShellError>> {
-    stream_to_cloud_async(plugin, engine, source, url, span).await
+) -> Result<Upload, Box<ShellError>> {
+    let signals = engine.signals();
+    let provider = target.object_store.provider();
+    let mut write = start_multipart(target, size_hint).await?;
+    if let Some(size) = size_hint {
+        progress.add_total(size);
+    }
+
+    let written = generic_copy(source, &mut write, provider, progress, span, signals).await;
+    // A failing child may have written only part of its output. The copy dropped the
+    // source, so a child that is still writing to it exits as well and is always waited
+    // on. Its error is the cause of a failed copy, i.e. a pipe closed by a crash.
+    let written = match child.map(ChildProcess::wait) {
+        Some(Err(e)) => Err(Box::new(e)),
+        Some(Ok(())) | None => written,
+    };
+
+    finish_multipart(write, written, provider, progress, span).await
 }
 
-async fn stream_to_cloud_async(
-    plugin: &CloudPlugin,
-    engine: &EngineInterface,
-    source: impl Read,
-    url: &Spanned<Url>,
-    span: Span,
-) -> Result<(), Box<ShellError>> {
-    let signals = engine.signals();
-    let (object_store, path) = plugin.parse_url(engine, url, span).await?;
-    let upload = object_store
+async fn start_multipart(
+    target: Target,
+    size_hint: Option<u64>,
+) -> Result<PartWriter, Box<ShellError>> {
+    let opts = PutMultipartOptions {
+        attributes: target.attributes,
+        ..PutMultipartOptions::default()
+    };
+    let provider = target.object_store.provider();
+    let upload = target
+        .object_store
         .object_store()
-        .put_multipart(&path)
+        .put_multipart_opts(&target.path, opts)
         .await
-        .unwrap();
-    let mut write = WriteMultipart::new(upload);
+        .map_err(|e| CloudError::new("Could not start upload", e, provider, target.span))?;
+    Ok(PartWriter::new(
+        upload,
+        target.part_size,
+        target.concurrency,
+        size_hint,
+    ))
+}
 
-    let _ = generic_copy(source, &mut write, span, signals)?;
+/// Completes a multipart upload once everything was written. If writing failed or was
+/// interrupted, the upload is aborted instead, so that no incomplete upload is left
+/// behind in the bucket.
+async fn finish_multipart(
+    mut write: PartWriter,
+    written: Result<u64, Box<ShellError>>,
+    provider: Provider,
+    progress: &Progress,
+    span: Span,
+) -> Result<Upload, Box<ShellError>> {
+    let size = match written {
+        Ok(size) => size,
+        Err(e) => {
+            abort_multipart(&mut write).await;
+            return Err(e);
+        }
+    };
+
+    let finished = write.finish().await;
+    progress.finish();
+    match finished {
+        Ok(result) => Ok(Upload {
+            result,
+            size,
+            multipart: true,
+        }),
+        Err(e) => {
+            abort_multipart(&mut write).await;
+            Err(Box::new(write_error(e, provider, span)))
+        }
+    }
+}
 
-    let _ = write.finish().await.map_err(|e| ShellError::GenericError {
-        error: format!("Could not write to S3: {e}"),
-        msg: "".into(),
-        span: None,
-        help: None,
-        inner: vec![],
-    })?;
+async fn abort_multipart(write: &mut PartWriter) {
+    if let Err(e) = write.abort().await {
+        warn!("Could not abort multipart upload: {e}");
+    }
+}
 
-    Ok(())
+fn write_error(e: object_store::Error, provider: Provider, span: Span) -> ShellError {
+    CloudError::new("Could not write file", e, provider, span).into()
 }
 
 const DEFAULT_BUF_SIZE: usize = 8192;
 
 // Copied from [`std::io::copy`]
-fn generic_copy(
+async fn generic_copy(
     mut reader: impl Read,
-    writer: &mut WriteMultipart,
+    writer: &mut PartWriter,
+    provider: Provider,
+    progress: &Progress,
     span: Span,
     signals: &Signals,
 ) -> Result<u64, Box<ShellError>> {
@@ -218,7 +618,11 @@ fn generic_copy(
             Err(e) => return Err(Box::new(ShellError::Io(IoError::new(e, span, None)))),
         };
         len += n;
-        writer.write(&buf[..n]);
+        progress.advance(n as u64);
+        writer
+            .write(&buf[..n])
+            .await
+            .map_err(|e| write_error(e, provider, span))?;
     }
     Ok(len as u64)
 }
@@ -294,27 +698,45 @@ fn convert_to_extension(
     }
 }
 
+/// Uploads bytes with a single put. Progress is reported once the put completed, as a
+/// single request reports nothing while it's sent.
 async fn stream_bytes(
-    plugin: &CloudPlugin,
-    engine: &EngineInterface,
     bytes: Vec<u8>,
-    url: &Spanned<Url>,
-    span: Span,
-) -> Result<(), ShellError> {
-    let (object_store, path) = plugin.parse_url(engine, url, span).await?;
-
-    let payload = PutPayload::from_bytes(Bytes::from(bytes));
-    object_store
-        .object_store()
-        .put(&path, payload)
-        .await
-        .map_err(|e| ShellError::GenericError {
-            error: format!("Could not write to S3: {e}"),
-            msg: "".into(),
-            span: None,
-            help: None,
-            inner: vec![],
-        })?;
-
-    Ok(())
+    target: Target,
+    progress: &Progress,
+) -> Result<Upload, ShellError> {
+    let size = bytes.len() as u64;
+    progress.add_total(size);
+    let opts = PutOptions {
+        mode: target.mode,
+        attributes: target.attributes,
+        ..PutOptions::default()
+    };
+    let span = target.span;
+    let result = match &target.raw_key {
+        Some(key) => {
+            target
+                .object_store
+                .put_raw(key, Bytes::from(bytes), &opts, span)
+                .await?
+        }
+        None => target
+            .object_store
+            .object_store()
+            .put_opts(
+                &target.path,
+                PutPayload::from_bytes(Bytes::from(bytes)),
+                opts,
+            )
+            .await
+            .map_err(|e| write_error(e, target.object_store.provider(), span))?,
+    };
+    progress.advance(size);
+    progress.finish();
+
+    Ok(Upload {
+        result,
+        size,
+        multipart: false,
+    })
 }
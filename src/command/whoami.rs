@@ -0,0 +1,90 @@
+use nu_plugin::{EngineInterface, EvaluatedCall, PluginCommand};
+use nu_protocol::{
+    Category, Example, LabeledError, PipelineData, ShellError, Signature, Span, SyntaxShape, Type,
+    Value, record,
+};
+
+use crate::{CloudPlugin, providers};
+
+use super::{parse_url_arg, resolve_alias};
+
+pub struct Whoami;
+
+impl PluginCommand for Whoami {
+    type Plugin = CloudPlugin;
+
+    fn name(&self) -> &str {
+        "cloud whoami"
+    }
+
+    fn signature(&self) -> nu_protocol::Signature {
+        Signature::build("cloud whoami")
+            .input_output_types(vec![(Type::Nothing, Type::record())])
+            .required(
+                "url",
+                SyntaxShape::String,
+                "A url of the store to show the identity for.",
+            )
+            .category(Category::FileSystem)
+    }
+
+    fn description(&self) -> &str {
+        "Show the provider, profile, region, endpoint and credentials used to access a url."
+    }
+
+    fn extra_description(&self) -> &str {
+        "The access key id is masked except for its last four characters. Secrets are never shown."
+    }
+
+    fn examples(&self) -> Vec<Example<'_>> {
+        vec![Example {
+            description: "Show which credentials are used to access an s3 bucket.",
+            example: "cloud whoami s3://mybucket/",
+            result: None,
+        }]
+    }
+
+    fn run(
+        &self,
+        plugin: &Self::Plugin,
+        engine: &EngineInterface,
+        call: &EvaluatedCall,
+        _input: PipelineData,
+    ) -> Result<PipelineData, LabeledError> {
+        plugin
+            .rt
+            .block_on(command(engine, call))
+            .map_err(|e| LabeledError::from(*e))
+    }
+}
+
+async fn command(
+    engine: &EngineInterface,
+    call: &EvaluatedCall,
+) -> Result<PipelineData, Box<ShellError>> {
+    let call_span = call.head;
+    let url = resolve_alias(engine, call.req(0)?)?;
+    let url = parse_url_arg(engine, &url, call_span)?;
+    let identity = providers::identity(&url, call_span).await?;
+
+    let string = |s: Option<String>| optional(s.map(|s| Value::string(s, call_span)), call_span);
+    Ok(PipelineData::Value(
+        Value::record(
+            record!(
+                "provider" => Value::string(identity.provider, call_span),
+                "profile" => string(identity.profile),
+                "region" => string(identity.region),
+                "endpoint" => string(identity.endpoint),
+                "credential_source" => string(identity.credential_source.map(String::from)),
+                "access_key_id" => string(identity.access_key_id),
+                "expires" => optional(identity.expires.map(|e| Value::date(e.fixed_offset(), call_span)), call_span),
+            ),
+            call_span,
+        ),
+        None,
+    ))
+}
+
+fn optional(value: Option<Value>, span: Span) -> Value {
+    value.unwrap_or(Value::nothing(span))
+}
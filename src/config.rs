@@ -0,0 +1,149 @@
+use std::{collections::BTreeMap, time::Duration};
+
+use nu_plugin::EngineInterface;
+use nu_protocol::{ShellError, Value};
+
+use crate::{command::has_scheme, providers::ClientSettings};
+
+/// How long the caches are kept without being used, unless configured otherwise
+const DEFAULT_IDLE_TIMEOUT: Duration = Duration::from_secs(10 * 60);
+
+/// Schemes of the supported stores, which can't be used as alias names
+pub const RESERVED_SCHEMES: [&str; 12] = [
+    "s3", "s3a", "gs", "az", "adl", "azure", "abfs", "abfss", "http", "https", "memory", "file",
+];
+
+/// Settings from `$env.config.plugins.cloud`, i.e.
+///
+/// ```nushell
+/// $env.config.plugins.cloud = { retries: 3, timeout: 1min, proxy: "http://proxy:3128" }
+/// ```
+#[derive(Debug, Clone)]
+pub struct PluginConfig {
+    pub client: ClientSettings,
+    pub aliases: Aliases,
+    /// Time without any use after which the caches are dropped and plugin GC is enabled
+    /// again. Zero keeps the caches until `cloud cache-clear`.
+    pub idle_timeout: Duration,
+}
+
+impl Default for PluginConfig {
+    fn default() -> Self {
+        PluginConfig {
+            client: ClientSettings::default(),
+            aliases: Aliases::default(),
+            idle_timeout: DEFAULT_IDLE_TIMEOUT,
+        }
+    }
+}
+
+impl PluginConfig {
+    pub fn load(engine: &EngineInterface) -> Result<Self, Box<ShellError>> {
+        match engine.get_plugin_config()? {
+            Some(value) => Self::from_value(value),
+            None => Ok(PluginConfig::default()),
+        }
+    }
+
+    fn from_value(value: Value) -> Result<Self, Box<ShellError>> {
+        let record = match value {
+            Value::Nothing { .. } => return Ok(PluginConfig::default()),
+            Value::Error { error, .. } => return Err(error),
+            value => value.into_record()?,
+        };
+
+        let mut config = PluginConfig::default();
+        for (key, value) in record.iter() {
+            if key == "idle_timeout" {
+                config.idle_timeout = u64::try_from(value.as_duration()?)
+                    .map(Duration::from_nanos)
+                    .map_err(|_| ShellError::IncorrectValue {
+                        msg: "idle_timeout must not be negative".into(),
+                        val_span: value.span(),
+                        call_span: value.span(),
+                    })?;
+            } else if key == "aliases" {
+                config.aliases = Aliases::from_value(value)?;
+            } else if !config.client.set(key, value)? {
+                return Err(Box::new(ShellError::GenericError {
+                    error: format!("Unknown cloud plugin setting {key}"),
+                    msg: "".into(),
+                    span: Some(value.span()),
+                    help: Some(format!(
+                        "Supported settings are idle_timeout, aliases, {}",
+                        crate::providers::CLIENT_SETTINGS.join(", ")
+                    )),
+                    inner: vec![],
+                }));
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// Short names for urls, used like a scheme, i.e. `logs:2024/` for
+/// `s3://mybucket/logs/2024/` with
+///
+/// ```nushell
+/// $env.config.plugins.cloud = { aliases: { logs: "s3://mybucket/logs/" } }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Aliases(BTreeMap<String, String>);
+
+impl Aliases {
+    pub fn from_value(value: &Value) -> Result<Self, Box<ShellError>> {
+        let mut aliases = BTreeMap::new();
+        for (name, url) in value.as_record()?.iter() {
+            let invalid = |msg: String| {
+                Box::new(ShellError::IncorrectValue {
+                    msg,
+                    val_span: url.span(),
+                    call_span: value.span(),
+                })
+            };
+            if !has_scheme(&format!("{name}:")) || RESERVED_SCHEMES.contains(&name.as_str()) {
+                return Err(invalid(format!(
+                    "Alias {name} must be a name like a url scheme, other than {}",
+                    RESERVED_SCHEMES.join(", ")
+                )));
+            }
+            let url = url.coerce_str()?;
+            if !has_scheme(&url) {
+                return Err(invalid(format!(
+                    "Alias {name} must be a url, i.e. s3://bucket/"
+                )));
+            }
+            aliases.insert(name.to_lowercase(), url.to_string());
+        }
+        Ok(Aliases(aliases))
+    }
+
+    /// Returns the names of the aliases and their urls, sorted by name
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0
+            .iter()
+            .map(|(name, url)| (name.as_str(), url.as_str()))
+    }
+
+    /// Splits a url starting with an alias into the alias name, its url ending with `/`
+    /// and the rest of the url
+    pub fn split<'a>(&'a self, url: &'a str) -> Option<(&'a str, String, &'a str)> {
+        let (name, rest) = url.split_once(':')?;
+        let (name, target) = self.0.get_key_value(&name.to_lowercase())?;
+        let base = match target.ends_with('/') {
+            true => target.clone(),
+            false => format!("{target}/"),
+        };
+        Some((name, base, rest.trim_start_matches('/')))
+    }
+
+    /// Returns the url of a url starting with an alias, or [`None`] if it doesn't
+    pub fn resolve(&self, url: &str) -> Option<String> {
+        let (name, base, rest) = self.split(url)?;
+        match rest.is_empty() {
+            // The alias may name a single file
+            true => self.0.get(name).cloned(),
+            false => Some(format!("{base}{rest}")),
+        }
+    }
+}
@@ -0,0 +1,179 @@
+use nu_protocol::{LabeledError, ShellError, Span};
+
+use crate::providers::Provider;
+
+/// An error returned by an object store while operating on a url.
+///
+/// Converting it into a [`ShellError`] points at the url that caused it and, for the
+/// common failure cases, adds an error code that scripts can match on and a hint on how
+/// to fix it.
+pub struct CloudError {
+    /// What was being done, i.e. "Could not read file"
+    action: String,
+    error: object_store::Error,
+    /// The storage the error came from, which the hints depend on
+    provider: Provider,
+    /// Span of the url
+    span: Span,
+}
+
+impl CloudError {
+    pub fn new(
+        action: impl Into<String>,
+        error: object_store::Error,
+        provider: Provider,
+        span: Span,
+    ) -> Self {
+        CloudError {
+            action: action.into(),
+            error,
+            provider,
+            span,
+        }
+    }
+}
+
+/// The common failure cases, which have an error code
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Failure {
+    NotFound,
+    AlreadyExists,
+    Precondition,
+    PermissionDenied,
+    Unauthenticated,
+    NotSupported,
+}
+
+impl Failure {
+    fn of(error: &object_store::Error) -> Option<Self> {
+        use object_store::Error;
+
+        match error {
+            Error::NotFound { .. } => Some(Failure::NotFound),
+            Error::AlreadyExists { .. } => Some(Failure::AlreadyExists),
+            Error::Precondition { .. } => Some(Failure::Precondition),
+            Error::PermissionDenied { .. } => Some(Failure::PermissionDenied),
+            Error::Unauthenticated { .. } => Some(Failure::Unauthenticated),
+            Error::NotImplemented { .. } => Some(Failure::NotSupported),
+            _ => None,
+        }
+    }
+
+    /// Returns the error code, label and help of the failure in provider
+    fn describe(self, provider: Provider) -> (&'static str, &'static str, &'static str) {
+        match self {
+            Failure::NotFound => (
+                "not_found",
+                "file not found",
+                "Use `cloud ls` to see which files exist",
+            ),
+            Failure::AlreadyExists => (
+                "already_exists",
+                "file already exists",
+                "Remove the file first, or save without --no-clobber (--if-not-exists) to overwrite it",
+            ),
+            Failure::Precondition => (
+                "precondition_failed",
+                "file was changed since its etag was read",
+                "Read the file and its current etag again before retrying",
+            ),
+            Failure::PermissionDenied => (
+                "permission_denied",
+                "access denied",
+                match provider {
+                    Provider::AmazonS3 => {
+                        "Check that the credentials of your profile (AWS_PROFILE) grant access to this bucket and key"
+                    }
+                    Provider::GoogleCloudStorage => {
+                        "Check that the service account (GOOGLE_APPLICATION_CREDENTIALS) grants access to this bucket and object"
+                    }
+                    Provider::MicrosoftAzure => {
+                        "Check that your Azure credentials grant access to this container and blob"
+                    }
+                    Provider::Local => "Check the permissions of the file and its directories",
+                    Provider::Memory | Provider::Http => {
+                        "Check that your credentials grant access to this url"
+                    }
+                },
+            ),
+            Failure::Unauthenticated => (
+                "unauthenticated",
+                "not authenticated",
+                match provider {
+                    Provider::AmazonS3 => {
+                        "Refresh your credentials, i.e. run `aws sso login` for SSO profiles"
+                    }
+                    Provider::GoogleCloudStorage => {
+                        "Refresh your credentials, i.e. run `gcloud auth application-default login`"
+                    }
+                    Provider::MicrosoftAzure => "Refresh your credentials, i.e. run `az login`",
+                    Provider::Local | Provider::Memory | Provider::Http => {
+                        "Check the credentials used to access this url"
+                    }
+                },
+            ),
+            Failure::NotSupported => (
+                "not_supported",
+                "not supported by this storage type",
+                match provider {
+                    Provider::AmazonS3 => {
+                        "The bucket or its endpoint does not support this operation"
+                    }
+                    _ => "Use a storage type that supports this operation, such as s3",
+                },
+            ),
+        }
+    }
+
+    /// Returns the error of a failure while doing action, with the error code and a hint.
+    /// details is the message of the underlying error.
+    pub fn into_error(
+        self,
+        action: &str,
+        details: String,
+        provider: Provider,
+        span: Span,
+    ) -> ShellError {
+        let (code, label, help) = self.describe(provider);
+        let details = ShellError::GenericError {
+            error: details,
+            msg: "".into(),
+            span: None,
+            help: None,
+            inner: vec![],
+        };
+        ShellError::LabeledError(Box::new(
+            LabeledError::new(format!("{action}: {label}"))
+                .with_label(label, span)
+                .with_code(format!("nu_plugin_cloud::{code}"))
+                .with_help(help)
+                .with_inner(details),
+        ))
+    }
+}
+
+impl From<CloudError> for ShellError {
+    fn from(value: CloudError) -> Self {
+        match Failure::of(&value.error) {
+            Some(failure) => failure.into_error(
+                &value.action,
+                value.error.to_string(),
+                value.provider,
+                value.span,
+            ),
+            None => ShellError::GenericError {
+                error: format!("{}: {}", value.action, value.error),
+                msg: "".into(),
+                span: Some(value.span),
+                help: None,
+                inner: vec![],
+            },
+        }
+    }
+}
+
+impl From<CloudError> for Box<ShellError> {
+    fn from(value: CloudError) -> Self {
+        Box::new(value.into())
+    }
+}
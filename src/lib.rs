@@ -1,12 +1,15 @@
 mod cache;
 mod command;
+mod config;
+mod error;
+mod progress;
 mod providers;
 
 use cache::Cache;
 use nu_plugin::{EngineInterface, Plugin};
 use nu_protocol::{ShellError, Span, Spanned};
 use object_store::path::Path;
-use providers::NuObjectStore;
+use providers::{ClientSettings, NuObjectStore};
 use tokio::runtime::Runtime;
 use url::Url;
 
@@ -29,9 +32,10 @@ impl CloudPlugin {
         &self,
         engine: &EngineInterface,
         url: &Spanned<Url>,
+        settings: &ClientSettings,
         span: Span,
     ) -> Result<(NuObjectStore, Path), ShellError> {
-        providers::parse_url(engine, &self.cache, url, span).await
+        providers::parse_url(engine, &self.cache, url, settings, span).await
     }
 }
 
@@ -0,0 +1,105 @@
+use std::{
+    io::Write,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use nu_protocol::Filesize;
+
+/// How often progress is reported. Transfers finishing faster than this are not reported.
+const REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Reports the progress of a transfer on stderr: the bytes transferred, throughput and,
+/// when the total size is known, the estimated time remaining.
+pub struct Progress {
+    enabled: bool,
+    label: String,
+    state: Mutex<ProgressState>,
+}
+
+struct ProgressState {
+    started: Instant,
+    last_report: Option<Instant>,
+    transferred: u64,
+    total: Option<u64>,
+}
+
+impl Progress {
+    pub fn new(label: impl Into<String>, enabled: bool) -> Self {
+        Progress {
+            enabled,
+            label: label.into(),
+            state: Mutex::new(ProgressState {
+                started: Instant::now(),
+                last_report: None,
+                transferred: 0,
+                total: None,
+            }),
+        }
+    }
+
+    /// Adds to the total number of bytes that will be transferred
+    pub fn add_total(&self, size: u64) {
+        if let Ok(mut state) = self.state.lock() {
+            state.total = Some(state.total.unwrap_or_default() + size);
+        }
+    }
+
+    /// Records that n more bytes were transferred, reporting progress if it's time to
+    pub fn advance(&self, n: u64) {
+        if !self.enabled {
+            return;
+        }
+        let Ok(mut state) = self.state.lock() else {
+            return;
+        };
+        state.transferred += n;
+
+        let since = state.last_report.unwrap_or(state.started);
+        if since.elapsed() >= REPORT_INTERVAL {
+            state.last_report = Some(Instant::now());
+            self.report(&state, false);
+        }
+    }
+
+    /// Reports the final progress, if progress was reported before
+    pub fn finish(&self) {
+        if let Ok(state) = self.state.lock()
+            && self.enabled
+            && state.last_report.is_some()
+        {
+            self.report(&state, true);
+        }
+    }
+
+    fn report(&self, state: &ProgressState, done: bool) {
+        let elapsed = state.started.elapsed().as_secs_f64().max(0.001);
+        let rate = state.transferred as f64 / elapsed;
+
+        let mut line = format!(
+            "{}: {}",
+            self.label,
+            Filesize::new(state.transferred as i64)
+        );
+        if let Some(total) = state.total {
+            line.push_str(&format!(" of {}", Filesize::new(total as i64)));
+        }
+        line.push_str(&format!(", {}/s", Filesize::new(rate as i64)));
+        if done {
+            line.push_str(&format!(", done in {}s", elapsed.round()));
+        } else if let Some(total) = state.total
+            && rate > 0.0
+        {
+            let remaining = total.saturating_sub(state.transferred) as f64 / rate;
+            line.push_str(&format!(", ETA {}s", remaining.round()));
+        }
+
+        let mut stderr = std::io::stderr().lock();
+        // Pad the line to overwrite longer previous reports
+        let _ = write!(stderr, "\r{line:<80}");
+        if done {
+            let _ = writeln!(stderr);
+        }
+        let _ = stderr.flush();
+    }
+}
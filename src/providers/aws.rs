@@ -1,21 +1,58 @@
-use std::{error::Error, sync::Arc};
+use std::{collections::HashMap, error::Error, sync::Arc, time::Duration};
 
-use aws_config::{BehaviorVersion, SdkConfig};
+use aws_config::{BehaviorVersion, Region, SdkConfig};
 use aws_credential_types::{Credentials, provider::ProvideCredentials};
+use aws_sdk_s3::{
+    Client,
+    error::{DisplayErrorContext, ProvideErrorMetadata},
+    primitives::ByteStream,
+};
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
 use itertools::Itertools;
 use nu_plugin::EngineInterface;
-use nu_protocol::{ShellError, Spanned};
-use object_store::aws::AmazonS3Builder;
+use nu_protocol::{ShellError, Span, Spanned};
+use object_store::{
+    Attribute, GetOptions, GetRange, PutMode, PutOptions, PutResult, aws::AmazonS3Builder,
+    path::Path,
+};
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, utf8_percent_encode};
 use url::Url;
 
-use crate::cache::{Cache, ObjectStoreCacheKey};
+use crate::{
+    cache::{Cache, ObjectStoreCacheKey},
+    error::Failure,
+};
 
-use super::NuObjectStore;
+use super::{
+    Check, CheckStatus, ClientSettings, Identity, NuObjectStore, ObjectVersion, Provider,
+    VersionMarker,
+    aws_config_file::{AwsConfigFile, profile_name},
+    aws_http_client::S3HttpClient,
+};
+
+/// Credentials expiring sooner than this are reported by `cloud doctor`
+const EXPIRY_WARNING: Duration = Duration::from_secs(15 * 60);
+
+/// The request timeout of object_store, used unless one is set
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The largest object a single CopyObject request can copy
+const MAX_COPY_SIZE: i64 = 5 * 1024 * 1024 * 1024;
+
+/// Characters that need to be encoded in the key of a CopyObject copy source
+const COPY_SOURCE_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'/')
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
 
 pub async fn build_object_store(
     engine: &EngineInterface,
     cache: &Cache,
     url: &Spanned<Url>,
+    settings: &ClientSettings,
 ) -> Result<NuObjectStore, ShellError> {
     let aws_config = aws_load_config().await;
 
@@ -51,9 +88,15 @@ pub async fn build_object_store(
         });
     };
 
+    // Like object_store, the sdk sends requests to the endpoint of the url if it has one
+    let endpoint = parsed_info
+        .endpoint
+        .or_else(|| aws_config.endpoint_url().map(ToString::to_string));
     let cache_key = ObjectStoreCacheKey::AmazonS3 {
         bucket: bucket.clone(),
         region: region.clone(),
+        endpoint: endpoint.clone(),
+        settings: Arc::new(settings.clone()),
     };
 
     if let Some(object_store) = cache.get_store(&cache_key).await {
@@ -61,7 +104,15 @@ pub async fn build_object_store(
     } else {
         let builder = AmazonS3Builder::new()
             .with_url(url.item.clone())
-            .with_region(region.clone());
+            .with_region(region.clone())
+            .with_client_options(settings.client_options(url.span).map_err(|e| *e)?)
+            .with_retry(settings.retry_config());
+        let builder = match &endpoint {
+            Some(endpoint) => builder
+                .with_endpoint(endpoint)
+                .with_allow_http(endpoint.starts_with("http:")),
+            None => builder,
+        };
 
         let builder = if let Some(credentials) = aws_creds(&aws_config).await? {
             let builder = builder
@@ -74,13 +125,7 @@ pub async fn build_object_store(
                 builder
             }
         } else {
-            return Err(ShellError::GenericError {
-                error: "Could not determine AWS credentials from environment".into(),
-                msg: "".into(),
-                span: Some(url.span),
-                help: None,
-                inner: vec![],
-            });
+            return Err(no_credentials_error(url.span));
         };
 
         let s3 = builder.build().map_err(|e| ShellError::GenericError {
@@ -91,10 +136,18 @@ pub async fn build_object_store(
             inner: vec![],
         })?;
 
+        let client = s3_client(
+            &aws_config,
+            &region,
+            endpoint.as_deref(),
+            settings,
+            url.span,
+        )
+        .map_err(|e| *e)?;
         let object_store = NuObjectStore::AmazonS3 {
             store: Arc::new(s3),
             bucket,
-            region,
+            client,
         };
 
         cache
@@ -104,6 +157,682 @@ pub async fn build_object_store(
     }
 }
 
+/// Lists a page of the versions and delete markers of the objects below prefix, starting
+/// at marker. Returns the marker of the next page unless it was the last one.
+///
+/// object_store has no support for object versions, so the S3 sdk is used directly.
+pub async fn list_versions(
+    client: &Client,
+    bucket: &str,
+    prefix: &Path,
+    marker: Option<VersionMarker>,
+    span: Span,
+) -> Result<(Vec<ObjectVersion>, Option<VersionMarker>), ShellError> {
+    let marker = marker.unwrap_or_default();
+    let output = client
+        .list_object_versions()
+        .bucket(bucket)
+        .prefix(prefix.as_ref())
+        .set_key_marker(marker.key)
+        .set_version_id_marker(marker.version)
+        .send()
+        .await
+        .map_err(|e| s3_error("Could not list object versions", e, span))?;
+
+    let mut versions: Vec<ObjectVersion> = output
+        .versions()
+        .iter()
+        .map(|v| ObjectVersion {
+            key: v.key().unwrap_or_default().to_string(),
+            version: v.version_id().map(ToString::to_string),
+            is_latest: v.is_latest().unwrap_or_default(),
+            delete_marker: false,
+            size: v.size().map(|size| size as u64),
+            last_modified: v.last_modified().and_then(to_chrono),
+            e_tag: v.e_tag().map(ToString::to_string),
+        })
+        .collect();
+    versions.extend(output.delete_markers().iter().map(|d| ObjectVersion {
+        key: d.key().unwrap_or_default().to_string(),
+        version: d.version_id().map(ToString::to_string),
+        is_latest: d.is_latest().unwrap_or_default(),
+        delete_marker: true,
+        size: None,
+        last_modified: d.last_modified().and_then(to_chrono),
+        e_tag: None,
+    }));
+
+    // S3 prefixes match on characters, object_store prefixes on path segments
+    versions.retain(|v| {
+        prefix.as_ref().is_empty()
+            || v.key == prefix.as_ref()
+            || v.key.starts_with(&format!("{prefix}/"))
+    });
+    // Pages are in key order, only the versions and delete markers of a page are merged
+    versions.sort_by(|a, b| {
+        a.key
+            .cmp(&b.key)
+            .then_with(|| b.last_modified.cmp(&a.last_modified))
+    });
+
+    let next = (output.is_truncated() == Some(true)).then(|| VersionMarker {
+        key: output.next_key_marker().map(ToString::to_string),
+        version: output.next_version_id_marker().map(ToString::to_string),
+    });
+    Ok((versions, next))
+}
+
+/// Reads an object, or a range or version of it, with the S3 sdk. Used for keys that
+/// object_store can't represent, such as `a//b` or `a/../b`.
+pub async fn get_object(
+    client: &Client,
+    bucket: &str,
+    key: &str,
+    opts: &GetOptions,
+    span: Span,
+) -> Result<Bytes, ShellError> {
+    let range = opts.range.as_ref().map(|range| match range {
+        GetRange::Bounded(range) => format!("bytes={}-{}", range.start, range.end - 1),
+        GetRange::Offset(offset) => format!("bytes={offset}-"),
+        GetRange::Suffix(len) => format!("bytes=-{len}"),
+    });
+    let output = client
+        .get_object()
+        .bucket(bucket)
+        .key(key)
+        .set_version_id(opts.version.clone())
+        .set_range(range)
+        .send()
+        .await
+        .map_err(|e| s3_error("Could not read object", e, span))?;
+    let body = output
+        .body
+        .collect()
+        .await
+        .map_err(|e| sdk_error("Could not read object", e, span))?;
+    Ok(body.into_bytes())
+}
+
+/// Writes an object with the S3 sdk, for keys that object_store can't represent
+pub async fn put_object(
+    client: &Client,
+    bucket: &str,
+    key: &str,
+    bytes: Bytes,
+    opts: &PutOptions,
+    span: Span,
+) -> Result<PutResult, ShellError> {
+    let mut request = client
+        .put_object()
+        .bucket(bucket)
+        .key(key)
+        .body(ByteStream::from(bytes));
+    for (attribute, value) in &opts.attributes {
+        let value = value.to_string();
+        request = match attribute {
+            Attribute::ContentType => request.content_type(value),
+            Attribute::CacheControl => request.cache_control(value),
+            Attribute::ContentEncoding => request.content_encoding(value),
+            Attribute::ContentDisposition => request.content_disposition(value),
+            Attribute::ContentLanguage => request.content_language(value),
+            Attribute::Metadata(name) => request.metadata(name.to_string(), value),
+            _ => request,
+        };
+    }
+    request = match &opts.mode {
+        PutMode::Overwrite => request,
+        PutMode::Create => request.if_none_match("*"),
+        PutMode::Update(version) => request.set_if_match(version.e_tag.clone()),
+    };
+    let output = request
+        .send()
+        .await
+        .map_err(|e| match (&opts.mode, e.code()) {
+            // Like object_store, a failed create means that the object exists already
+            (PutMode::Create, Some("PreconditionFailed")) => Failure::AlreadyExists.into_error(
+                "Could not write object",
+                DisplayErrorContext(&e).to_string(),
+                Provider::AmazonS3,
+                span,
+            ),
+            _ => s3_error("Could not write object", e, span),
+        })?;
+    Ok(PutResult {
+        e_tag: output.e_tag().map(ToString::to_string),
+        version: output.version_id().map(ToString::to_string),
+    })
+}
+
+/// Deletes an object with the S3 sdk, or permanently deletes a single version of it
+pub async fn delete_object(
+    client: &Client,
+    bucket: &str,
+    key: &str,
+    version: Option<&str>,
+    span: Span,
+) -> Result<(), ShellError> {
+    let error = match version {
+        Some(_) => "Could not delete object version",
+        None => "Could not delete object",
+    };
+    client
+        .delete_object()
+        .bucket(bucket)
+        .key(key)
+        .set_version_id(version.map(ToString::to_string))
+        .send()
+        .await
+        .map_err(|e| s3_error(error, e, span))?;
+    Ok(())
+}
+
+/// Copies an old version of an object over the current version, making it the latest.
+/// Returns the id of the newly created version.
+///
+/// A single copy request copies at most [`MAX_COPY_SIZE`] bytes, larger versions are
+/// rejected before copying.
+pub async fn restore_version(
+    client: &Client,
+    bucket: &str,
+    path: &Path,
+    version: &str,
+    span: Span,
+) -> Result<Option<String>, ShellError> {
+    let error = "Could not restore object version";
+    let head = client
+        .head_object()
+        .bucket(bucket)
+        .key(path.as_ref())
+        .version_id(version)
+        .send()
+        .await
+        .map_err(|e| s3_error(error, e, span))?;
+    let size = head.content_length().unwrap_or_default();
+    if size > MAX_COPY_SIZE {
+        return Err(ShellError::GenericError {
+            error: format!("{error}: the version is larger than 5 GiB"),
+            msg: format!("version is {}", nu_protocol::Filesize::new(size)),
+            span: Some(span),
+            help: Some(format!(
+                "S3 copies at most 5 GiB at once, save the version again instead: cloud open --raw --version {version} <url> | cloud save --raw <url>"
+            )),
+            inner: vec![],
+        });
+    }
+
+    let copy_source = format!(
+        "{bucket}/{}?versionId={version}",
+        utf8_percent_encode(path.as_ref(), COPY_SOURCE_ENCODE_SET)
+    );
+    let output = client
+        .copy_object()
+        .bucket(bucket)
+        .key(path.as_ref())
+        .copy_source(copy_source)
+        .send()
+        .await
+        .map_err(|e| s3_error(error, e, span))?;
+    Ok(output.version_id().map(ToString::to_string))
+}
+
+/// Returns the names of the buckets of the configured account, sorted
+pub async fn list_buckets(
+    settings: &ClientSettings,
+    span: Span,
+) -> Result<Vec<String>, ShellError> {
+    let aws_config = aws_load_config().await;
+    // ListBuckets returns the buckets of every region
+    let region = aws_config
+        .region()
+        .map(|region| region.to_string())
+        .unwrap_or_else(|| "us-east-1".into());
+    let output = s3_client(&aws_config, &region, None, settings, span)
+        .map_err(|e| *e)?
+        .list_buckets()
+        .send()
+        .await
+        .map_err(|e| s3_error("Could not list buckets", e, span))?;
+    let mut buckets: Vec<String> = output
+        .buckets()
+        .iter()
+        .filter_map(|bucket| bucket.name().map(ToString::to_string))
+        .collect();
+    buckets.sort();
+    Ok(buckets)
+}
+
+pub async fn identity(url: &Spanned<Url>) -> Result<Identity, ShellError> {
+    let aws_config = aws_load_config().await;
+    let region = aws_config
+        .region()
+        .map(ToString::to_string)
+        .or(parse_url_parts(&url.item).region);
+    let credentials = match aws_creds(&aws_config).await {
+        Ok(Some(credentials)) => credentials,
+        Ok(None) => return Err(no_credentials_error(url.span)),
+        Err(e) => {
+            return Err(ShellError::GenericError {
+                error: "Could not resolve AWS credentials".into(),
+                msg: "".into(),
+                span: Some(url.span),
+                help: Some("Run `cloud doctor` to check your AWS configuration".into()),
+                inner: vec![e],
+            });
+        }
+    };
+
+    let access_key_id = credentials.access_key_id();
+    let suffix = &access_key_id[access_key_id.len().saturating_sub(4)..];
+    Ok(Identity {
+        provider: "s3",
+        profile: Some(profile_name()),
+        endpoint: aws_config
+            .endpoint_url()
+            .map(ToString::to_string)
+            .or_else(|| {
+                region
+                    .as_ref()
+                    .map(|region| format!("https://s3.{region}.amazonaws.com"))
+            }),
+        region,
+        credential_source: Some(credential_source()),
+        access_key_id: Some(format!("****{suffix}")),
+        expires: credentials.expiry().map(DateTime::<Utc>::from),
+    })
+}
+
+/// Runs the checks of `cloud doctor`: the config file and SSO profile layout, region,
+/// credentials and their expiry, and if a bucket is given, clock skew and whether the
+/// bucket can be listed.
+pub async fn diagnose(url: Option<&Url>, settings: &ClientSettings) -> Vec<Check> {
+    let parts = url.map(parse_url_parts);
+    let bucket = parts.as_ref().and_then(|p| p.bucket.as_deref());
+    let mut checks = vec![];
+    let profile = profile_name();
+
+    let config = match AwsConfigFile::config() {
+        Ok(config) => {
+            checks.push(Check::ok(
+                "config file",
+                format!("found {}", config.path.display()),
+            ));
+            Some(config)
+        }
+        Err(path) => {
+            checks.push(Check::new(
+                "config file",
+                CheckStatus::Warning,
+                format!("{} does not exist", path.display()),
+                "Run `aws configure` or `aws configure sso` to create it",
+            ));
+            None
+        }
+    };
+
+    let profile_settings = config.as_ref().and_then(|c| c.profile(&profile));
+    match (&config, profile_settings) {
+        (_, Some(_)) => checks.push(Check::ok("profile", format!("using profile {profile}"))),
+        (Some(config), None) if profile != "default" => checks.push(Check::new(
+            "profile",
+            CheckStatus::Error,
+            format!(
+                "profile {profile} from AWS_PROFILE is not in {}",
+                config.path.display()
+            ),
+            "Set AWS_PROFILE to one of the profiles in the config file",
+        )),
+        _ => checks.push(Check::ok(
+            "profile",
+            "using the default profile".to_string(),
+        )),
+    }
+
+    match (&config, profile_settings) {
+        (Some(config), Some(settings)) if settings.keys().any(|k| k.starts_with("sso_")) => {
+            checks.push(check_sso_layout(config, settings));
+        }
+        _ => checks.push(Check::skipped("sso profile", "profile doesn't use SSO")),
+    }
+
+    let aws_config = aws_load_config().await;
+    let region = aws_config
+        .region()
+        .map(ToString::to_string)
+        .or(parts.as_ref().and_then(|p| p.region.clone()));
+    match &region {
+        Some(region) => checks.push(Check::ok("region", format!("using {region}"))),
+        None => checks.push(Check::new(
+            "region",
+            CheckStatus::Error,
+            "no region is configured".into(),
+            "Set region in your profile, or set AWS_REGION",
+        )),
+    }
+
+    let credentials = match aws_creds(&aws_config).await {
+        Ok(Some(credentials)) => {
+            checks.push(Check::ok(
+                "credentials",
+                format!("resolved from {}", credential_source()),
+            ));
+            Some(credentials)
+        }
+        Ok(None) => {
+            checks.push(Check::new(
+                "credentials",
+                CheckStatus::Error,
+                "no credentials provider is configured".into(),
+                "Configure a profile with `aws configure`, or set AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY",
+            ));
+            None
+        }
+        Err(e) => {
+            checks.push(Check::new(
+                "credentials",
+                CheckStatus::Error,
+                e.to_string(),
+                login_fix(profile_settings),
+            ));
+            None
+        }
+    };
+
+    match credentials.as_ref().map(|c| c.expiry()) {
+        None => checks.push(Check::skipped("credential expiry", "no credentials")),
+        Some(None) => checks.push(Check::ok(
+            "credential expiry",
+            "credentials don't expire".into(),
+        )),
+        Some(Some(expiry)) => {
+            let expires = DateTime::<Utc>::from(expiry);
+            match expiry.duration_since(std::time::SystemTime::now()) {
+                Ok(left) if left > EXPIRY_WARNING => checks.push(Check::ok(
+                    "credential expiry",
+                    format!("valid until {expires}"),
+                )),
+                Ok(_) => checks.push(Check::new(
+                    "credential expiry",
+                    CheckStatus::Warning,
+                    format!("credentials expire soon, at {expires}"),
+                    login_fix(profile_settings),
+                )),
+                Err(_) => checks.push(Check::new(
+                    "credential expiry",
+                    CheckStatus::Error,
+                    format!("credentials expired at {expires}"),
+                    login_fix(profile_settings),
+                )),
+            }
+        }
+    }
+
+    match (bucket, &region, &credentials) {
+        (Some(bucket), Some(region), Some(_)) => {
+            let endpoint = parts
+                .as_ref()
+                .and_then(|p| p.endpoint.clone())
+                .or_else(|| aws_config.endpoint_url().map(ToString::to_string));
+            let client = s3_client(
+                &aws_config,
+                region,
+                endpoint.as_deref(),
+                settings,
+                Span::unknown(),
+            );
+            checks.extend(check_bucket(client, bucket).await);
+        }
+        (None, _, _) => {
+            checks.push(Check::skipped("clock skew", "no bucket url given"));
+            checks.push(Check::skipped("bucket", "no bucket url given"));
+        }
+        _ => {
+            checks.push(Check::skipped("clock skew", "no region or credentials"));
+            checks.push(Check::skipped("bucket", "no region or credentials"));
+        }
+    }
+
+    checks
+}
+
+/// Checks that the profile only references an sso-session with the start url and region,
+/// the layout the aws_config crate requires.
+fn check_sso_layout(config: &AwsConfigFile, settings: &HashMap<String, String>) -> Check {
+    let Some(session_name) = settings.get("sso_session") else {
+        return Check::new(
+            "sso profile",
+            CheckStatus::Error,
+            "profile uses the legacy SSO layout without sso_session".into(),
+            "Run `aws configure sso` to move sso_start_url into an [sso-session] section",
+        );
+    };
+    if settings.contains_key("sso_start_url") {
+        return Check::new(
+            "sso profile",
+            CheckStatus::Error,
+            "profile sets both sso_session and sso_start_url".into(),
+            "Remove sso_start_url from the profile, it belongs in the [sso-session] section",
+        );
+    }
+    let missing: Vec<&str> = ["sso_account_id", "sso_role_name"]
+        .into_iter()
+        .filter(|key| !settings.contains_key(*key))
+        .collect();
+    if !missing.is_empty() {
+        return Check::new(
+            "sso profile",
+            CheckStatus::Error,
+            format!("profile is missing {}", missing.join(", ")),
+            "Add the missing settings to the profile, or run `aws configure sso`",
+        );
+    }
+
+    let Some(session) = config.sso_session(session_name) else {
+        return Check::new(
+            "sso profile",
+            CheckStatus::Error,
+            format!("[sso-session {session_name}] section is missing"),
+            "Add the sso-session section with sso_start_url and sso_region",
+        );
+    };
+    let missing: Vec<&str> = ["sso_start_url", "sso_region"]
+        .into_iter()
+        .filter(|key| !session.contains_key(*key))
+        .collect();
+    if !missing.is_empty() {
+        return Check::new(
+            "sso profile",
+            CheckStatus::Error,
+            format!(
+                "[sso-session {session_name}] is missing {}",
+                missing.join(", ")
+            ),
+            "Add the missing settings to the sso-session section",
+        );
+    }
+
+    Check::ok("sso profile", format!("using sso-session {session_name}"))
+}
+
+/// Lists a single key of the bucket, which also fails when the local clock is too far off
+async fn check_bucket(client: Result<Client, Box<ShellError>>, bucket: &str) -> Vec<Check> {
+    let client = match client {
+        Ok(client) => client,
+        Err(e) => {
+            return vec![
+                Check::skipped("clock skew", "no http client"),
+                Check::new(
+                    "bucket",
+                    CheckStatus::Error,
+                    e.to_string(),
+                    "Check the client settings, i.e. the ca_bundle file",
+                ),
+            ];
+        }
+    };
+    let result = client
+        .list_objects_v2()
+        .bucket(bucket)
+        .max_keys(1)
+        .send()
+        .await;
+
+    let Err(e) = result else {
+        return vec![
+            Check::ok("clock skew", "within the 15 minutes allowed by s3".into()),
+            Check::ok("bucket", format!("{bucket} can be listed")),
+        ];
+    };
+
+    match e.code() {
+        Some("RequestTimeTooSkewed") => vec![
+            Check::new(
+                "clock skew",
+                CheckStatus::Error,
+                "the local clock differs from s3 by more than 15 minutes".into(),
+                "Synchronize the system clock, i.e. enable NTP",
+            ),
+            Check::skipped("bucket", "clock skew"),
+        ],
+        code => {
+            let fix = match code {
+                Some("NoSuchBucket") => "Check the bucket name in the url",
+                Some("AccessDenied") => {
+                    "Check that the role of your profile is allowed to list the bucket"
+                }
+                Some("PermanentRedirect") | Some("AuthorizationHeaderMalformed") => {
+                    "Set the region of your profile to the region of the bucket"
+                }
+                _ => "Check the network connection and the bucket name",
+            };
+            vec![
+                Check::ok("clock skew", "s3 did not report clock skew".into()),
+                Check::new(
+                    "bucket",
+                    CheckStatus::Error,
+                    format!("{bucket} can't be listed: {}", DisplayErrorContext(e)),
+                    fix,
+                ),
+            ]
+        }
+    }
+}
+
+fn login_fix(profile: Option<&HashMap<String, String>>) -> &'static str {
+    if profile.is_some_and(|p| p.keys().any(|k| k.starts_with("sso_"))) {
+        "Run `aws sso login` to refresh your SSO session"
+    } else {
+        "Refresh your credentials, or set AWS_PROFILE to a profile with valid credentials"
+    }
+}
+
+/// Determines where aws_config loads credentials from, following its provider chain
+fn credential_source() -> &'static str {
+    if std::env::var_os("AWS_ACCESS_KEY_ID").is_some() {
+        return "environment";
+    }
+    if std::env::var_os("AWS_WEB_IDENTITY_TOKEN_FILE").is_some() {
+        return "web identity token";
+    }
+
+    let profile = profile_name();
+    let config = AwsConfigFile::config().ok();
+    let settings = config.as_ref().and_then(|c| c.profile(&profile));
+    if let Some(settings) = settings {
+        if settings.contains_key("sso_session") || settings.contains_key("sso_start_url") {
+            return "sso";
+        }
+        if settings.contains_key("role_arn") {
+            return "assume role";
+        }
+        if settings.contains_key("credential_process") {
+            return "credential process";
+        }
+    }
+    if AwsConfigFile::credentials()
+        .ok()
+        .is_some_and(|c| c.credentials_profile(&profile).is_some())
+    {
+        return "shared credentials file";
+    }
+    if settings.is_some_and(|s| s.contains_key("aws_access_key_id")) {
+        return "config file";
+    }
+    "container or instance metadata"
+}
+
+fn no_credentials_error(span: Span) -> ShellError {
+    ShellError::GenericError {
+        error: "Could not determine AWS credentials from environment".into(),
+        msg: "".into(),
+        span: Some(span),
+        help: None,
+        inner: vec![],
+    }
+}
+
+/// Creates an sdk client that uses the endpoint, proxy, CA bundle, timeouts and retries of
+/// the object stores created with them
+fn s3_client(
+    aws_config: &SdkConfig,
+    region: &str,
+    endpoint: Option<&str>,
+    settings: &ClientSettings,
+    span: Span,
+) -> Result<Client, Box<ShellError>> {
+    let retry = settings.retry_config();
+    let retry_config = aws_sdk_s3::config::retry::RetryConfig::standard()
+        .with_max_attempts(u32::try_from(retry.max_retries).unwrap_or(u32::MAX - 1) + 1)
+        .with_initial_backoff(retry.backoff.init_backoff)
+        .with_max_backoff(retry.backoff.max_backoff);
+    // The sdk has no retry timeout, so it bounds the whole operation instead
+    let timeout_config = aws_sdk_s3::config::timeout::TimeoutConfig::builder()
+        .operation_timeout(retry.retry_timeout)
+        .operation_attempt_timeout(settings.timeout.unwrap_or(DEFAULT_TIMEOUT))
+        .build();
+    let mut config = aws_sdk_s3::config::Builder::from(aws_config)
+        .region(Region::new(region.to_string()))
+        .http_client(S3HttpClient::new(settings, span)?)
+        .retry_config(retry_config)
+        .timeout_config(timeout_config);
+    // object_store addresses buckets by path on other endpoints than the one of AWS
+    if let Some(endpoint) = endpoint {
+        config = config.endpoint_url(endpoint).force_path_style(true);
+    }
+    let config = config.build();
+    Ok(Client::from_conf(config))
+}
+
+/// Returns the error of a failed S3 request. The codes that object_store maps to its
+/// errors get the same error codes and hints, see [`CloudError`](crate::error::CloudError).
+fn s3_error(error: &str, e: impl Error + ProvideErrorMetadata, span: Span) -> ShellError {
+    let failure = match e.code() {
+        Some("NoSuchKey" | "NoSuchVersion" | "NotFound") => Failure::NotFound,
+        Some("AccessDenied") => Failure::PermissionDenied,
+        Some("PreconditionFailed") => Failure::Precondition,
+        _ => return sdk_error(error, e, span),
+    };
+    failure.into_error(
+        error,
+        DisplayErrorContext(&e).to_string(),
+        Provider::AmazonS3,
+        span,
+    )
+}
+
+fn sdk_error(error: &str, e: impl Error, span: Span) -> ShellError {
+    ShellError::GenericError {
+        error: format!("{error}: {}", DisplayErrorContext(e)),
+        msg: "".into(),
+        span: Some(span),
+        help: None,
+        inner: vec![],
+    }
+}
+
+fn to_chrono(date: &aws_sdk_s3::primitives::DateTime) -> Option<DateTime<Utc>> {
+    DateTime::from_timestamp(date.secs(), date.subsec_nanos())
+}
+
 async fn aws_load_config() -> SdkConfig {
     aws_config::load_defaults(BehaviorVersion::latest()).await
 }
@@ -132,6 +861,8 @@ async fn aws_creds(aws_config: &SdkConfig) -> Result<Option<Credentials>, ShellE
 struct ParsedInfo {
     bucket: Option<String>,
     region: Option<String>,
+    /// The endpoint of S3 compatible storage, such as R2
+    endpoint: Option<String>,
 }
 
 // This is borrowed from the iternals of the AmazonS3 builder.
@@ -143,7 +874,7 @@ fn parse_url_parts(url: &Url) -> ParsedInfo {
     match url.scheme() {
         "s3" | "s3a" => ParsedInfo {
             bucket: Some(host.to_string()),
-            region: None,
+            ..Default::default()
         },
         "https" => match host.splitn(4, '.').collect_tuple() {
             Some(("s3", region, "amazonaws", "com")) => {
@@ -153,23 +884,33 @@ fn parse_url_parts(url: &Url) -> ParsedInfo {
                 ParsedInfo {
                     bucket: bucket.map(|s| s.to_string()),
                     region,
+                    endpoint: None,
                 }
             }
             Some((bucket, "s3", region, "amazonaws.com")) => {
                 let bucket = Some(bucket.to_string());
                 let region = Some(region.to_string());
 
-                ParsedInfo { bucket, region }
+                ParsedInfo {
+                    bucket,
+                    region,
+                    endpoint: None,
+                }
             }
-            Some((_account, "r2", "cloudflarestorage", "com")) => {
+            Some((account, "r2", "cloudflarestorage", "com")) => {
                 let region = Some("auto".to_string());
+                let endpoint = Some(format!("https://{account}.r2.cloudflarestorage.com"));
                 let bucket = url
                     .path_segments()
                     .into_iter()
                     .flatten()
                     .next()
                     .map(ToString::to_string);
-                ParsedInfo { bucket, region }
+                ParsedInfo {
+                    bucket,
+                    region,
+                    endpoint,
+                }
             }
             _ => ParsedInfo::default(),
         },
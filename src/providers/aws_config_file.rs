@@ -0,0 +1,79 @@
+use std::{collections::HashMap, path::PathBuf};
+
+/// A minimal reader for the AWS shared config and credentials files, used to explain
+/// which profile and credential source aws_config picks up.
+pub struct AwsConfigFile {
+    pub path: PathBuf,
+    sections: HashMap<String, HashMap<String, String>>,
+}
+
+impl AwsConfigFile {
+    /// Loads `~/.aws/config`, or the file set in AWS_CONFIG_FILE
+    pub fn config() -> Result<Self, PathBuf> {
+        Self::load(file_path("AWS_CONFIG_FILE", "config"))
+    }
+
+    /// Loads `~/.aws/credentials`, or the file set in AWS_SHARED_CREDENTIALS_FILE
+    pub fn credentials() -> Result<Self, PathBuf> {
+        Self::load(file_path("AWS_SHARED_CREDENTIALS_FILE", "credentials"))
+    }
+
+    fn load(path: PathBuf) -> Result<Self, PathBuf> {
+        let Ok(contents) = std::fs::read_to_string(&path) else {
+            return Err(path);
+        };
+
+        let mut sections: HashMap<String, HashMap<String, String>> = HashMap::new();
+        let mut current = None;
+        for line in contents.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+                continue;
+            }
+            if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+                sections.entry(name.clone()).or_default();
+                current = Some(name);
+            } else if let (Some(section), Some((key, value))) = (&current, line.split_once('=')) {
+                sections
+                    .entry(section.clone())
+                    .or_default()
+                    .insert(key.trim().to_string(), value.trim().to_string());
+            }
+        }
+        Ok(AwsConfigFile { path, sections })
+    }
+
+    /// Returns the settings of a profile in the config file
+    pub fn profile(&self, name: &str) -> Option<&HashMap<String, String>> {
+        if name == "default" {
+            self.sections
+                .get("default")
+                .or_else(|| self.sections.get("profile default"))
+        } else {
+            self.sections.get(&format!("profile {name}"))
+        }
+    }
+
+    /// Returns the settings of a profile in the credentials file, which has no `profile` prefix
+    pub fn credentials_profile(&self, name: &str) -> Option<&HashMap<String, String>> {
+        self.sections.get(name)
+    }
+
+    pub fn sso_session(&self, name: &str) -> Option<&HashMap<String, String>> {
+        self.sections.get(&format!("sso-session {name}"))
+    }
+}
+
+/// The profile selected by AWS_PROFILE, or `default`
+pub fn profile_name() -> String {
+    std::env::var("AWS_PROFILE").unwrap_or_else(|_| "default".into())
+}
+
+fn file_path(env_var: &str, name: &str) -> PathBuf {
+    match std::env::var(env_var) {
+        Ok(path) => nu_path::expand_tilde(path),
+        Err(_) => nu_path::home_dir()
+            .map(|home| home.join(".aws").join(name).into_std_path_buf())
+            .unwrap_or_else(|| PathBuf::from(".aws").join(name)),
+    }
+}
@@ -0,0 +1,176 @@
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use aws_smithy_runtime_api::client::{
+    http::{
+        HttpClient, HttpConnector, HttpConnectorFuture, HttpConnectorSettings, SharedHttpConnector,
+    },
+    orchestrator::{HttpRequest, HttpResponse},
+    result::ConnectorError,
+    runtime_components::RuntimeComponents,
+};
+use aws_smithy_types::body::SdkBody;
+use http::Uri;
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+use hyper_util::{
+    client::{
+        legacy::{
+            Client,
+            connect::{HttpConnector as TcpConnector, proxy::Tunnel},
+        },
+        proxy::matcher::Matcher,
+    },
+    rt::{TokioExecutor, TokioIo},
+};
+use nu_protocol::{ShellError, Span};
+use rustls::{
+    ClientConfig, RootCertStore,
+    pki_types::{CertificateDer, pem::PemObject},
+};
+use tokio::net::TcpStream;
+use tower_service::Service;
+
+use super::{ClientSettings, client::Http2};
+
+/// The connect timeout of object_store, used unless one is set
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// The http client of the S3 sdk, configured with the proxy, CA bundle, connect timeout and
+/// http2 settings like the http client of object_store.
+///
+/// The sdk's own client ignores the settings, and the client of object_store can't be used
+/// as it resolves `.` and `..` segments in the path of requests, which are part of the key.
+#[derive(Debug, Clone)]
+pub struct S3HttpClient(Client<HttpsConnector<ProxyConnector>, SdkBody>);
+
+impl S3HttpClient {
+    /// Creates the client of settings, reading the CA bundle if one is set
+    pub fn new(settings: &ClientSettings, span: Span) -> Result<Self, Box<ShellError>> {
+        let mut roots = RootCertStore::empty();
+        roots.add_parsable_certificates(rustls_native_certs::load_native_certs().certs);
+        if let Some(pem) = settings.read_ca_bundle(span)? {
+            for certificate in CertificateDer::pem_slice_iter(&pem) {
+                certificate
+                    .map_err(|e| e.to_string())
+                    .and_then(|certificate| roots.add(certificate).map_err(|e| e.to_string()))
+                    .map_err(|e| settings.ca_bundle_error(e, span))?;
+            }
+        }
+        let tls =
+            ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+                .with_safe_default_protocol_versions()
+                .map_err(|e| {
+                    Box::new(ShellError::GenericError {
+                        error: format!("Could not create http client: {e}"),
+                        msg: "".into(),
+                        span: Some(span),
+                        help: None,
+                        inner: vec![],
+                    })
+                })?
+                .with_root_certificates(roots)
+                .with_no_client_auth();
+
+        let mut tcp = TcpConnector::new();
+        tcp.enforce_http(false);
+        tcp.set_connect_timeout(Some(
+            settings.connect_timeout.unwrap_or(DEFAULT_CONNECT_TIMEOUT),
+        ));
+        // Like reqwest, the proxy environment variables are used unless a proxy is set
+        let proxy = match &settings.proxy {
+            Some(proxy) => Matcher::builder()
+                .all(proxy.clone())
+                .no(settings.no_proxy.clone().unwrap_or_default())
+                .build(),
+            None => Matcher::from_env(),
+        };
+        let connector = ProxyConnector {
+            tcp,
+            proxy: Arc::new(proxy),
+        };
+
+        let builder = HttpsConnectorBuilder::new()
+            .with_tls_config(tls)
+            .https_or_http();
+        let https = match settings.http2 {
+            None | Some(Http2::Never) => builder.enable_http1().wrap_connector(connector),
+            Some(Http2::Allow) => builder.enable_all_versions().wrap_connector(connector),
+            Some(Http2::Only) => builder.enable_http2().wrap_connector(connector),
+        };
+        let client = Client::builder(TokioExecutor::new())
+            .http2_only(matches!(settings.http2, Some(Http2::Only)))
+            .build(https);
+        Ok(S3HttpClient(client))
+    }
+}
+
+impl HttpClient for S3HttpClient {
+    // The timeouts of the settings are applied by the client and the sdk config
+    fn http_connector(
+        &self,
+        _settings: &HttpConnectorSettings,
+        _components: &RuntimeComponents,
+    ) -> SharedHttpConnector {
+        SharedHttpConnector::new(self.clone())
+    }
+}
+
+impl HttpConnector for S3HttpClient {
+    fn call(&self, request: HttpRequest) -> HttpConnectorFuture {
+        let client = self.0.clone();
+        HttpConnectorFuture::new(async move {
+            let request = request
+                .try_into_http1x()
+                .map_err(|e| ConnectorError::user(e.into()))?;
+            let response = client.request(request).await.map_err(|e| {
+                if e.is_connect() {
+                    ConnectorError::io(e.into()).never_connected()
+                } else {
+                    ConnectorError::io(e.into())
+                }
+            })?;
+            HttpResponse::try_from(response.map(SdkBody::from_body_1_x))
+                .map_err(|e| ConnectorError::other(e.into(), None))
+        })
+    }
+}
+
+/// Connects to the destination directly, or through a tunnel of the proxy matching it
+#[derive(Debug, Clone)]
+pub struct ProxyConnector {
+    tcp: TcpConnector,
+    proxy: Arc<Matcher>,
+}
+
+impl Service<Uri> for ProxyConnector {
+    type Response = TokioIo<TcpStream>;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, BoxError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), BoxError>> {
+        self.tcp.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, dst: Uri) -> Self::Future {
+        match self.proxy.intercept(&dst) {
+            Some(intercept) => {
+                let mut tunnel = Tunnel::new(intercept.uri().clone(), self.tcp.clone());
+                if let Some(auth) = intercept.basic_auth() {
+                    tunnel = tunnel.with_auth(auth.clone());
+                }
+                Box::pin(async move { tunnel.call(dst).await.map_err(Into::into) })
+            }
+            None => {
+                let mut tcp = self.tcp.clone();
+                Box::pin(async move { tcp.call(dst).await.map_err(Into::into) })
+            }
+        }
+    }
+}
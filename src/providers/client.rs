@@ -0,0 +1,186 @@
+use std::{path::PathBuf, time::Duration};
+
+use nu_protocol::{ShellError, Span, Value};
+use object_store::{BackoffConfig, Certificate, ClientOptions, RetryConfig};
+
+/// Settings of the http client used to access cloud storage.
+///
+/// Unset settings keep the object_store defaults. Stores without an http client, such as
+/// the memory and local stores, ignore them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ClientSettings {
+    /// Maximum number of retries of a failed request, 0 disables retries
+    pub retries: Option<usize>,
+    /// Time after the first attempt after which a request is no longer retried
+    pub retry_timeout: Option<Duration>,
+    /// Backoff before the first retry, doubled for every further retry
+    pub init_backoff: Option<Duration>,
+    pub max_backoff: Option<Duration>,
+    pub connect_timeout: Option<Duration>,
+    /// Timeout of a whole request, including reading the response body
+    pub timeout: Option<Duration>,
+    pub proxy: Option<String>,
+    /// Comma separated hosts, domains and networks that are accessed without the proxy
+    pub no_proxy: Option<String>,
+    /// PEM file with additional root certificates to trust
+    pub ca_bundle: Option<PathBuf>,
+    pub http2: Option<Http2>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Http2 {
+    /// Only use HTTP/1, the object_store default
+    Never,
+    /// Use HTTP/2 when the server supports it
+    Allow,
+    /// Only use HTTP/2
+    Only,
+}
+
+/// The setting names, as used in the plugin config. Flags use the same names with dashes.
+pub const CLIENT_SETTINGS: [&str; 10] = [
+    "retries",
+    "retry_timeout",
+    "init_backoff",
+    "max_backoff",
+    "connect_timeout",
+    "timeout",
+    "proxy",
+    "no_proxy",
+    "ca_bundle",
+    "http2",
+];
+
+impl ClientSettings {
+    /// Sets the setting called name to value. Returns false if there is no such setting.
+    pub fn set(&mut self, name: &str, value: &Value) -> Result<bool, Box<ShellError>> {
+        match name {
+            "retries" => self.retries = Some(non_negative(value)?),
+            "retry_timeout" => self.retry_timeout = Some(duration(value)?),
+            "init_backoff" => self.init_backoff = Some(duration(value)?),
+            "max_backoff" => self.max_backoff = Some(duration(value)?),
+            "connect_timeout" => self.connect_timeout = Some(duration(value)?),
+            "timeout" => self.timeout = Some(duration(value)?),
+            "proxy" => self.proxy = Some(value.coerce_string()?),
+            "no_proxy" => self.no_proxy = Some(string_list(value)?),
+            "ca_bundle" => {
+                self.ca_bundle = Some(nu_path::expand_tilde(value.coerce_str()?.as_ref()))
+            }
+            "http2" => self.http2 = Some(http2(value)?),
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    pub fn retry_config(&self) -> RetryConfig {
+        let default = RetryConfig::default();
+        RetryConfig {
+            backoff: BackoffConfig {
+                init_backoff: self.init_backoff.unwrap_or(default.backoff.init_backoff),
+                max_backoff: self.max_backoff.unwrap_or(default.backoff.max_backoff),
+                base: default.backoff.base,
+            },
+            max_retries: self.retries.unwrap_or(default.max_retries),
+            retry_timeout: self.retry_timeout.unwrap_or(default.retry_timeout),
+        }
+    }
+
+    /// Returns the client options, reading the CA bundle if one is set
+    pub fn client_options(&self, span: Span) -> Result<ClientOptions, Box<ShellError>> {
+        let mut options = ClientOptions::new();
+        if let Some(timeout) = self.connect_timeout {
+            options = options.with_connect_timeout(timeout);
+        }
+        if let Some(timeout) = self.timeout {
+            options = options.with_timeout(timeout);
+        }
+        if let Some(proxy) = &self.proxy {
+            options = options.with_proxy_url(proxy);
+        }
+        if let Some(no_proxy) = &self.no_proxy {
+            options = options.with_proxy_excludes(no_proxy);
+        }
+        options = match self.http2 {
+            None | Some(Http2::Never) => options,
+            Some(Http2::Allow) => options.with_allow_http2(),
+            Some(Http2::Only) => options.with_http2_only(),
+        };
+        if let Some(pem) = self.read_ca_bundle(span)? {
+            let certificates = Certificate::from_pem_bundle(&pem)
+                .map_err(|e| self.ca_bundle_error(e.to_string(), span))?;
+            for certificate in certificates {
+                options = options.with_root_certificate(certificate);
+            }
+        }
+        Ok(options)
+    }
+
+    /// Returns the contents of the CA bundle, if one is set
+    pub fn read_ca_bundle(&self, span: Span) -> Result<Option<Vec<u8>>, Box<ShellError>> {
+        self.ca_bundle
+            .as_ref()
+            .map(|path| std::fs::read(path).map_err(|e| self.ca_bundle_error(e.to_string(), span)))
+            .transpose()
+    }
+
+    pub fn ca_bundle_error(&self, error: String, span: Span) -> Box<ShellError> {
+        let path = self
+            .ca_bundle
+            .as_deref()
+            .unwrap_or(std::path::Path::new(""));
+        Box::new(ShellError::GenericError {
+            error: format!("Could not load CA bundle {}", path.display()),
+            msg: error,
+            span: Some(span),
+            help: Some("ca_bundle must be a file of PEM encoded certificates".into()),
+            inner: vec![],
+        })
+    }
+}
+
+fn non_negative(value: &Value) -> Result<usize, Box<ShellError>> {
+    let int = value.as_int()?;
+    usize::try_from(int).map_err(|_| invalid(value, "must not be negative"))
+}
+
+fn duration(value: &Value) -> Result<Duration, Box<ShellError>> {
+    let nanos = value.as_duration()?;
+    u64::try_from(nanos)
+        .map(Duration::from_nanos)
+        .map_err(|_| invalid(value, "must not be negative"))
+}
+
+/// Accepts a list of strings or a comma separated string
+fn string_list(value: &Value) -> Result<String, Box<ShellError>> {
+    match value {
+        Value::List { vals, .. } => Ok(vals
+            .iter()
+            .map(Value::coerce_str)
+            .collect::<Result<Vec<_>, _>>()?
+            .join(",")),
+        value => Ok(value.coerce_string()?),
+    }
+}
+
+fn http2(value: &Value) -> Result<Http2, Box<ShellError>> {
+    match value {
+        Value::Bool { val: true, .. } => Ok(Http2::Allow),
+        Value::Bool { val: false, .. } => Ok(Http2::Never),
+        value => match value.as_str()? {
+            "never" => Ok(Http2::Never),
+            "allow" => Ok(Http2::Allow),
+            "only" => Ok(Http2::Only),
+            _ => Err(invalid(value, "must be one of never, allow or only")),
+        },
+    }
+}
+
+fn invalid(value: &Value, msg: &str) -> Box<ShellError> {
+    Box::new(ShellError::GenericError {
+        error: "Invalid client setting".into(),
+        msg: msg.into(),
+        span: Some(value.span()),
+        help: None,
+        inner: vec![],
+    })
+}
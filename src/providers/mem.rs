@@ -1,21 +1,72 @@
 use super::NuObjectStore;
-use crate::cache::{Cache, ObjectStoreCacheKey};
+use crate::cache::Cache;
+use async_lock::{Mutex, MutexGuard};
 use nu_plugin::EngineInterface;
 use nu_protocol::ShellError;
 use object_store::memory::InMemory;
-use std::sync::Arc;
+use std::{collections::HashMap, sync::Arc};
+use url::Url;
+
+/// Name of the memory store of `memory:/` urls
+pub const DEFAULT_MEMORY_STORE: &str = "default";
+
+/// The memory stores, by name.
+///
+/// They hold data rather than clients, so unlike the other stores they are kept when the
+/// cache is cleared or expires, and plugin GC stays disabled while any of them exist.
+#[derive(Default)]
+pub struct MemoryStores {
+    stores: Mutex<HashMap<String, Arc<InMemory>>>,
+}
+
+impl MemoryStores {
+    /// Removes the store with the given name, or every store without a name.
+    /// Returns the removed stores.
+    pub async fn reset(&self, name: Option<&str>) -> Vec<(String, Arc<InMemory>)> {
+        let mut stores = self.lock().await;
+        let mut removed: Vec<(String, Arc<InMemory>)> = match name {
+            Some(name) => stores.remove_entry(name).into_iter().collect(),
+            None => stores.drain().collect(),
+        };
+        removed.sort_by(|a, b| a.0.cmp(&b.0));
+        removed
+    }
+
+    /// Returns the store with the given name, creating it if it doesn't exist
+    pub async fn get_or_create(
+        &self,
+        engine: &EngineInterface,
+        name: &str,
+    ) -> Result<Arc<InMemory>, ShellError> {
+        let mut stores = self.lock().await;
+        match stores.get(name) {
+            Some(store) => Ok(store.clone()),
+            None => {
+                let store = Arc::new(InMemory::new());
+                stores.insert(name.to_string(), store.clone());
+                engine.set_gc_disabled(true)?;
+                Ok(store)
+            }
+        }
+    }
+
+    pub async fn lock(&self) -> MutexGuard<'_, HashMap<String, Arc<InMemory>>> {
+        self.stores.lock().await
+    }
+}
+
+/// Returns the name of the memory store of url, i.e. `scratch` for `memory://scratch/file`
+pub fn store_name(url: &Url) -> &str {
+    url.host_str()
+        .filter(|host| !host.is_empty())
+        .unwrap_or(DEFAULT_MEMORY_STORE)
+}
 
 pub async fn build_object_store(
     engine: &EngineInterface,
     cache: &Cache,
+    url: &Url,
 ) -> Result<NuObjectStore, ShellError> {
-    let key = ObjectStoreCacheKey::Memory;
-    if let Some(store) = cache.get_store(&key).await {
-        Ok(store)
-    } else {
-        let store = InMemory::new();
-        let store = NuObjectStore::Memory(Arc::new(store));
-        cache.put_store(engine, key, store.clone()).await?;
-        Ok(store)
-    }
+    let store = cache.memory.get_or_create(engine, store_name(url)).await?;
+    Ok(NuObjectStore::Memory(store))
 }
@@ -1,11 +1,24 @@
 mod aws;
+mod aws_config_file;
+mod aws_http_client;
+mod client;
 mod local;
 mod mem;
 
+#[cfg(test)]
+pub use aws_http_client::S3HttpClient;
+pub use client::{CLIENT_SETTINGS, ClientSettings};
+pub use mem::{DEFAULT_MEMORY_STORE, MemoryStores};
+
 use crate::cache::Cache;
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
 use nu_plugin::EngineInterface;
 use nu_protocol::{ShellError, Span, Spanned};
-use object_store::{ObjectStore, ObjectStoreScheme, path::Path};
+use object_store::{
+    GetOptions, ObjectStore, ObjectStoreScheme, PutOptions, PutResult, aws::AmazonS3,
+    list::PaginatedListStore, path::Path, signer::Signer,
+};
 use std::sync::Arc;
 use url::Url;
 
@@ -14,9 +27,10 @@ pub enum NuObjectStore {
     Local(Arc<dyn ObjectStore>),
     Memory(Arc<dyn ObjectStore>),
     AmazonS3 {
-        store: Arc<dyn ObjectStore>,
+        store: Arc<AmazonS3>,
         bucket: String,
-        region: String,
+        /// The sdk client for requests object_store doesn't support, such as versions
+        client: aws_sdk_s3::Client,
     },
     #[allow(dead_code)]
     GoogleCloudStorage(Arc<dyn ObjectStore>),
@@ -25,6 +39,18 @@ pub enum NuObjectStore {
     #[allow(dead_code)]
     Http(Arc<dyn ObjectStore>),
 }
+
+/// The kind of storage behind a [`NuObjectStore`], i.e. to give provider specific help
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Provider {
+    Local,
+    Memory,
+    AmazonS3,
+    GoogleCloudStorage,
+    MicrosoftAzure,
+    Http,
+}
+
 impl NuObjectStore {
     pub fn object_store(&self) -> &dyn ObjectStore {
         match self {
@@ -36,39 +62,377 @@ impl NuObjectStore {
             NuObjectStore::Http(store) => store.as_ref(),
         }
     }
+
+    /// Returns the store if it can list one page at a time. Other stores fetch every page
+    /// of [`ObjectStore::list_with_delimiter`] at once.
+    pub fn paginated(&self) -> Option<&dyn PaginatedListStore> {
+        match self {
+            NuObjectStore::AmazonS3 { store, .. } => Some(store.as_ref()),
+            _ => None,
+        }
+    }
+
+    /// Lists a page of the versions and delete markers of the objects below prefix,
+    /// starting at marker. Returns the marker of the next page unless it was the last one.
+    pub async fn list_versions(
+        &self,
+        prefix: &Path,
+        marker: Option<VersionMarker>,
+        span: Span,
+    ) -> Result<(Vec<ObjectVersion>, Option<VersionMarker>), ShellError> {
+        match self {
+            NuObjectStore::AmazonS3 { bucket, client, .. } => {
+                aws::list_versions(client, bucket, prefix, marker, span).await
+            }
+            _ => Err(versions_unsupported(span)),
+        }
+    }
+
+    /// Permanently deletes a single version of an object
+    pub async fn delete_version(
+        &self,
+        path: &Path,
+        version: &str,
+        span: Span,
+    ) -> Result<(), ShellError> {
+        match self {
+            NuObjectStore::AmazonS3 { bucket, client, .. } => {
+                aws::delete_object(client, bucket, path.as_ref(), Some(version), span).await
+            }
+            _ => Err(versions_unsupported(span)),
+        }
+    }
+
+    /// Makes an old version of an object the latest version, returning the new version id
+    pub async fn restore_version(
+        &self,
+        path: &Path,
+        version: &str,
+        span: Span,
+    ) -> Result<Option<String>, ShellError> {
+        match self {
+            NuObjectStore::AmazonS3 { bucket, client, .. } => {
+                aws::restore_version(client, bucket, path, version, span).await
+            }
+            _ => Err(versions_unsupported(span)),
+        }
+    }
+
+    /// Reads an object by a key that [`Path`] can't represent, such as `a//b` or `a/../b`
+    pub async fn get_raw(
+        &self,
+        key: &str,
+        opts: &GetOptions,
+        span: Span,
+    ) -> Result<Bytes, ShellError> {
+        match self {
+            NuObjectStore::AmazonS3 { bucket, client, .. } => {
+                aws::get_object(client, bucket, key, opts, span).await
+            }
+            _ => Err(raw_keys_unsupported(span)),
+        }
+    }
+
+    /// Writes an object by a key that [`Path`] can't represent. Unlike
+    /// [`ObjectStore::put_opts`] the whole object is sent with a single request.
+    pub async fn put_raw(
+        &self,
+        key: &str,
+        bytes: Bytes,
+        opts: &PutOptions,
+        span: Span,
+    ) -> Result<PutResult, ShellError> {
+        match self {
+            NuObjectStore::AmazonS3 { bucket, client, .. } => {
+                aws::put_object(client, bucket, key, bytes, opts, span).await
+            }
+            _ => Err(raw_keys_unsupported(span)),
+        }
+    }
+
+    /// Deletes an object, or a single version of it, by a key that [`Path`] can't represent
+    pub async fn delete_raw(
+        &self,
+        key: &str,
+        version: Option<&str>,
+        span: Span,
+    ) -> Result<(), ShellError> {
+        match self {
+            NuObjectStore::AmazonS3 { bucket, client, .. } => {
+                aws::delete_object(client, bucket, key, version, span).await
+            }
+            _ => Err(raw_keys_unsupported(span)),
+        }
+    }
+
+    pub fn provider(&self) -> Provider {
+        match self {
+            NuObjectStore::Local(_) => Provider::Local,
+            NuObjectStore::Memory(_) => Provider::Memory,
+            NuObjectStore::AmazonS3 { .. } => Provider::AmazonS3,
+            NuObjectStore::GoogleCloudStorage(_) => Provider::GoogleCloudStorage,
+            NuObjectStore::MicrosoftAzure(_) => Provider::MicrosoftAzure,
+            NuObjectStore::Http(_) => Provider::Http,
+        }
+    }
+
+    /// Returns true if the store keeps multiple versions of objects
+    pub fn supports_versions(&self) -> bool {
+        matches!(self, NuObjectStore::AmazonS3 { .. })
+    }
+
+    /// Returns true if the store can save attributes such as the content type with objects
+    pub fn supports_attributes(&self) -> bool {
+        !matches!(self, NuObjectStore::Local(_))
+    }
+
+    /// Returns the signer used to generate presigned urls, if the store supports it
+    pub fn signer(&self) -> Option<&dyn Signer> {
+        match self {
+            NuObjectStore::AmazonS3 { store, .. } => Some(store.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+/// A version or delete marker of an object in a versioned bucket
+pub struct ObjectVersion {
+    pub key: String,
+    pub version: Option<String>,
+    pub is_latest: bool,
+    pub delete_marker: bool,
+    pub size: Option<u64>,
+    pub last_modified: Option<DateTime<Utc>>,
+    pub e_tag: Option<String>,
+}
+
+/// Where a listing of object versions continues
+#[derive(Default)]
+pub struct VersionMarker {
+    key: Option<String>,
+    version: Option<String>,
+}
+
+/// The identity used to access a store, as shown by `cloud whoami`.
+/// Only the provider is known for stores that don't need credentials.
+pub struct Identity {
+    pub provider: &'static str,
+    pub profile: Option<String>,
+    pub region: Option<String>,
+    pub endpoint: Option<String>,
+    pub credential_source: Option<&'static str>,
+    /// The access key id, with all but the last four characters masked
+    pub access_key_id: Option<String>,
+    pub expires: Option<DateTime<Utc>>,
+}
+
+impl Identity {
+    fn anonymous(provider: &'static str) -> Self {
+        Identity {
+            provider,
+            profile: None,
+            region: None,
+            endpoint: None,
+            credential_source: None,
+            access_key_id: None,
+            expires: None,
+        }
+    }
+}
+
+/// The outcome of a check run by `cloud doctor`
+pub struct Check {
+    pub name: &'static str,
+    pub status: CheckStatus,
+    pub detail: String,
+    /// How to fix a failed check
+    pub fix: Option<&'static str>,
+}
+
+pub enum CheckStatus {
+    Ok,
+    Warning,
+    Error,
+    Skipped,
+}
+
+impl CheckStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CheckStatus::Ok => "ok",
+            CheckStatus::Warning => "warning",
+            CheckStatus::Error => "error",
+            CheckStatus::Skipped => "skipped",
+        }
+    }
+}
+
+impl Check {
+    fn new(name: &'static str, status: CheckStatus, detail: String, fix: &'static str) -> Self {
+        Check {
+            name,
+            status,
+            detail,
+            fix: Some(fix),
+        }
+    }
+
+    fn ok(name: &'static str, detail: String) -> Self {
+        Check {
+            name,
+            status: CheckStatus::Ok,
+            detail,
+            fix: None,
+        }
+    }
+
+    fn skipped(name: &'static str, reason: &str) -> Self {
+        Check {
+            name,
+            status: CheckStatus::Skipped,
+            detail: format!("skipped, {reason}"),
+            fix: None,
+        }
+    }
+}
+
+pub fn versions_unsupported(span: Span) -> ShellError {
+    ShellError::GenericError {
+        error: "Object versions are not supported by this storage type".into(),
+        msg: "".into(),
+        span: Some(span),
+        help: Some(
+            "Object versions are only supported for s3 buckets with versioning enabled".into(),
+        ),
+        inner: vec![],
+    }
+}
+
+pub fn raw_keys_unsupported(span: Span) -> ShellError {
+    ShellError::GenericError {
+        error: "Unsupported key".into(),
+        msg: "".into(),
+        span: Some(span),
+        help: Some(
+            "Keys with empty segments, `.` or `..` segments or control characters can only be accessed in s3 buckets"
+                .into(),
+        ),
+        inner: vec![],
+    }
 }
 
 pub async fn parse_url(
     engine: &EngineInterface,
     cache: &Cache,
     url: &Spanned<Url>,
+    settings: &ClientSettings,
     span: Span,
 ) -> Result<(NuObjectStore, Path), ShellError> {
-    let (scheme, path) =
-        ObjectStoreScheme::parse(&url.item).map_err(|e| ShellError::IncorrectValue {
-            msg: format!("Unsupported url: {e}"),
-            val_span: url.span,
-            call_span: span,
-        })?;
-
-    let path = Path::parse(path).map_err(|e| ShellError::IncorrectValue {
-        msg: format!("Unsupported path: {e}"),
+    let (scheme, path) = parse_scheme(&url.item).map_err(|e| ShellError::IncorrectValue {
+        msg: format!("Unsupported url: {e}"),
         val_span: url.span,
         call_span: span,
     })?;
 
     let object_store = match scheme {
-        ObjectStoreScheme::AmazonS3 => aws::build_object_store(engine, cache, url).await?,
-        ObjectStoreScheme::Local => local::build_object_store(engine, cache).await?,
-        ObjectStoreScheme::Memory => mem::build_object_store(engine, cache).await?,
-        _ => {
-            return Err(ShellError::IncorrectValue {
-                msg: format!("Unsupported url: {}", url.item),
-                val_span: url.span,
-                call_span: span,
-            });
+        ObjectStoreScheme::AmazonS3 => {
+            aws::build_object_store(engine, cache, url, settings).await?
         }
+        ObjectStoreScheme::Local => local::build_object_store(engine, cache).await?,
+        ObjectStoreScheme::Memory => mem::build_object_store(engine, cache, &url.item).await?,
+        _ => return Err(unsupported_url(url, span)),
     };
 
     Ok((object_store, path))
 }
+
+/// Parses url like [`ObjectStoreScheme::parse`], additionally accepting named memory stores
+/// such as `memory://scratch/file`. The key is the percent-decoded path of the url.
+fn parse_scheme(url: &Url) -> object_store::Result<(ObjectStoreScheme, Path)> {
+    match (url.scheme(), url.host_str()) {
+        ("memory", Some(_)) => Ok((ObjectStoreScheme::Memory, Path::from_url_path(url.path())?)),
+        _ => Ok(ObjectStoreScheme::parse(url)?),
+    }
+}
+
+/// Returns the names of the s3 buckets that can be listed with the configured credentials
+pub async fn list_buckets(
+    settings: &ClientSettings,
+    span: Span,
+) -> Result<Vec<String>, ShellError> {
+    aws::list_buckets(settings, span).await
+}
+
+/// Resolves the identity used to access the store of url
+pub async fn identity(url: &Spanned<Url>, span: Span) -> Result<Identity, ShellError> {
+    match parse_scheme(&url.item) {
+        Ok((ObjectStoreScheme::AmazonS3, _)) => aws::identity(url).await,
+        Ok((ObjectStoreScheme::Local, _)) => Ok(Identity::anonymous("local")),
+        Ok((ObjectStoreScheme::Memory, _)) => Ok(Identity::anonymous("memory")),
+        _ => Err(unsupported_url(url, span)),
+    }
+}
+
+/// Checks the configuration needed to access the store of url. Without a url the s3
+/// configuration is checked, as it is the only provider that needs any.
+pub async fn diagnose(
+    url: Option<&Spanned<Url>>,
+    settings: &ClientSettings,
+    span: Span,
+) -> Result<Vec<Check>, ShellError> {
+    let Some(url) = url else {
+        return Ok(aws::diagnose(None, settings).await);
+    };
+    match parse_scheme(&url.item) {
+        Ok((ObjectStoreScheme::AmazonS3, _)) => Ok(aws::diagnose(Some(&url.item), settings).await),
+        Ok((ObjectStoreScheme::Local | ObjectStoreScheme::Memory, _)) => Ok(vec![Check::ok(
+            "provider",
+            "store needs no configuration".into(),
+        )]),
+        _ => Err(unsupported_url(url, span)),
+    }
+}
+
+fn unsupported_url(url: &Spanned<Url>, span: Span) -> ShellError {
+    ShellError::IncorrectValue {
+        msg: format!("Unsupported url: {}", url.item),
+        val_span: url.span,
+        call_span: span,
+    }
+}
+
+/// Returns the url of the root of the store that url points into, i.e. `s3://bucket/` for
+/// `s3://bucket/logs/file.log` where path is `logs/file.log`.
+pub fn store_root_url(url: &Url, path: &Path) -> Url {
+    let mut root = url.clone();
+    root.set_query(None);
+    root.set_fragment(None);
+
+    let segments: Vec<String> = url
+        .path_segments()
+        .into_iter()
+        .flatten()
+        .filter(|s| !s.is_empty())
+        .map(ToString::to_string)
+        .collect();
+    let keep = segments.len().saturating_sub(path.parts_count());
+
+    if let Ok(mut root_segments) = root.path_segments_mut() {
+        root_segments.clear().extend(&segments[..keep]);
+        if keep > 0 {
+            root_segments.push("");
+        }
+    }
+    root
+}
+
+/// Returns a url that can be used to re-open location in the store with the given root url.
+pub fn object_url(root: &Url, location: &Path) -> Url {
+    let mut url = root.clone();
+    if let Ok(mut segments) = url.path_segments_mut() {
+        segments
+            .pop_if_empty()
+            .extend(location.parts().map(|part| part.as_ref().to_string()));
+    }
+    url
+}